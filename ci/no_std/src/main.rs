@@ -0,0 +1,34 @@
+//! Runs a few frames of a ROM in a `#![no_std]` binary that has no `#[global_allocator]`, so
+//! linking fails as soon as the interpreter core needs `alloc` again.
+//!
+//! cargo build --target thumbv7em-none-eabihf
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+use chip8_emulator::Chip8;
+
+/// Draws the font 0 at the top left and halts.
+const ROM: [u8; 8] = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06];
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if let Ok(mut chip) = Chip8::builder().seed(1).build() {
+        if chip.load_program(&ROM).is_ok() {
+            let _ = chip.run_frames(2);
+            core::hint::black_box(chip.display_hash());
+        }
+    }
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
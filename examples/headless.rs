@@ -0,0 +1,33 @@
+//! Runs a ROM without SDL and prints the screen after every frame.
+//!
+//! cargo run --example headless --no-default-features --features std -- ROM [FRAMES]
+
+use std::env;
+
+use chip8_emulator::Chip8;
+
+const TICKS: usize = 10;
+const FRAMES: usize = 60;
+const USAGE: &str = "usage: headless ROM [FRAMES]";
+
+fn main() -> Result<(), String> {
+    let mut args = env::args().skip(1);
+    let rom = args.next().ok_or(USAGE)?;
+    let frames = match args.next() {
+        Some(value) => value.parse().map_err(|_| format!("invalid frame count {value}\n{USAGE}"))?,
+        None => FRAMES,
+    };
+
+    let mut emulator = Chip8::builder().ticks(TICKS).build().map_err(|err| err.to_string())?;
+    emulator.load_program_file(&rom).map_err(|err| err.to_string())?;
+
+    for frame in 0..frames {
+        emulator.run_frames(1).map_err(|err| err.to_string())?;
+        println!("frame {frame}");
+        print!("{}", emulator.render_ascii());
+        if emulator.is_halted() {
+            break;
+        }
+    }
+    Ok(())
+}
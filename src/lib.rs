@@ -1,12 +1,54 @@
-use std::fmt::{Formatter, write};
+#![cfg_attr(not(feature = "std"), no_std)]
 
-const RAM_SIZE: usize = 4096;
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
-pub const DISPLAY_WIDTH: usize = 64;
-pub const DISPLAY_HEIGHT: usize = 32;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+#[cfg(feature = "alloc")]
+use alloc::format;
+#[cfg(feature = "alloc")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::Formatter;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const RAM_SIZE: usize = 4096;
+pub const XO_CHIP_RAM_SIZE: usize = 65536;
+
+pub const DISPLAY_WIDTH: usize = 128;
+pub const DISPLAY_HEIGHT: usize = 64;
+pub const LOWRES_WIDTH: usize = 64;
+pub const LOWRES_HEIGHT: usize = 32;
 const VARIABLE_REGISTER_SIZE: usize = 16;
 const FLAG_REGISTER: usize = 15;
-const FONT: [u8; 80] = [
+const KEY_COUNT: usize = 16;
+const STACK_DEPTH: usize = 16;
+const FADE_STEP: u8 = 64;
+const PLANE_COUNT: usize = 2;
+const ALL_PLANES: u8 = 0b11;
+#[cfg(feature = "alloc")]
+const TRACE_HISTORY_SIZE: usize = 256;
+const RPL_FLAG_COUNT: usize = 8;
+const DEFAULT_CLOCK_HZ: u32 = 700;
+const TIMER_HZ: u128 = 60;
+#[cfg(feature = "alloc")]
+const TITLE_LIMIT: usize = 64;
+/// Address of the built-in 4x5 hex font that FX29 points into.
+pub const FONT_BASE: u16 = 0x050;
+pub const FONT_LEN: usize = 80;
+const PROGRAM_START: u16 = 0x200;
+pub const ETI_660_PROGRAM_START: u16 = 0x600;
+const FONT_CHAR_SIZE: u16 = 5;
+const FONT: [u8; FONT_LEN] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -24,26 +66,127 @@ const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
+const BIG_FONT_ADDRESS: u16 = 0x0A0;
+const BIG_FONT_CHAR_SIZE: u16 = 10;
+/// The 8x10 SUPER-CHIP digits 0-9 followed by the letters A-F that XO-CHIP added.
+const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xE7, 0xC0, 0xC0, 0xC0, 0xC0, 0xE7, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC7, 0xC3, 0xC3, 0xC3, 0xC3, 0xC7, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    OutOfBounds { addr: usize, size: usize },
+    DoesNotFit { len: usize, addr: usize, size: usize },
+    PixelOutOfBounds { x: usize, y: usize },
+    UnknownInstruction(u16),
+    StackUnderflow,
+    StackOverflow,
+    InvalidRegister(u8),
+    ProgramTooLarge { size: usize, max: usize },
+    EmptyProgram,
+    InvalidState,
+    PcOutOfBounds(u16),
+    #[cfg(feature = "std")]
+    Io(String),
+    InvalidMemorySize(usize),
+    InvalidFont { len: usize, expected: usize },
+    #[cfg(feature = "alloc")]
+    InvalidAssembly { line: usize, text: String },
+    InvalidOrigin(u16),
+    StateNotSerializable,
+}
+
+impl core::fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Chip8Error::OutOfBounds { addr, size } => write!(f, "index {addr} is out of bounds, memory size is {size}"),
+            Chip8Error::DoesNotFit { len, addr, size } => write!(f, "data {len} does not fit into memory {size} at {addr}"),
+            Chip8Error::PixelOutOfBounds { x, y } => write!(f, "{x}:{y} is out of bounds for the display"),
+            Chip8Error::UnknownInstruction(instruction) => write!(f, "unknown instruction:{:#06x}", instruction),
+            Chip8Error::StackUnderflow => write!(f, "stack is empty"),
+            Chip8Error::StackOverflow => write!(f, "stack is full, maximum depth is {STACK_DEPTH}"),
+            Chip8Error::InvalidRegister(register) => write!(f, "instruction contains invalid register {register}"),
+            Chip8Error::ProgramTooLarge { size, max } => write!(f, "could not load program: program has {size} bytes but at most {max} fit into memory"),
+            Chip8Error::EmptyProgram => write!(f, "could not load program: program is empty"),
+            Chip8Error::InvalidState => write!(f, "save state is corrupt"),
+            Chip8Error::PcOutOfBounds(pc) => write!(f, "program counter {:#06x} is odd or outside of memory", pc),
+            #[cfg(feature = "std")]
+            Chip8Error::Io(err) => write!(f, "could not read program: {err}"),
+            #[cfg(feature = "alloc")]
+            Chip8Error::InvalidAssembly { line, text } => write!(f, "could not assemble line {line}: {text}"),
+            Chip8Error::InvalidFont { len, expected } => write!(f, "font has {len} bytes but {expected} are required"),
+            Chip8Error::InvalidMemorySize(size) => write!(f, "memory size {size} is not between {RAM_SIZE} and {XO_CHIP_RAM_SIZE}"),
+            Chip8Error::StateNotSerializable => write!(f, "could not serialize the save state"),
+            Chip8Error::InvalidOrigin(origin) => write!(f, "could not load program: origin {:#06x} is odd or below {:#06x}", origin, PROGRAM_START),
+        }
+    }
+}
 
+impl Error for Chip8Error {}
+
+/// Always holds `XO_CHIP_RAM_SIZE` bytes so that no allocator is needed, only the first `size`
+/// of them are addressable.
+#[derive(Clone)]
 struct Memory {
-    inner: [u8; RAM_SIZE],
+    inner: [u8; XO_CHIP_RAM_SIZE],
+    size: usize,
 }
 
 impl Memory {
-    fn get_instruction(&self, pos: usize) -> Result<u16, String> {
-        let mut data = match self.inner.get(pos) {
+    fn new(size: usize) -> Result<Self, Chip8Error> {
+        if !(RAM_SIZE..=XO_CHIP_RAM_SIZE).contains(&size) {
+            return Err(Chip8Error::InvalidMemorySize(size));
+        }
+        Ok(Self { inner: [0u8; XO_CHIP_RAM_SIZE], size })
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.inner[..self.size]
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.inner[..self.size]
+    }
+
+    fn clear_from(&mut self, pos: usize) {
+        if let Some(region) = self.bytes_mut().get_mut(pos..) {
+            region.fill(0);
+        }
+    }
+
+    fn get_instruction(&self, pos: usize) -> Result<u16, Chip8Error> {
+        let mut data = match self.bytes().get(pos) {
             Some(d) => *d as u16,
             None => {
-                return Err(format!("index {pos} is out of bounds, memory size is {}", self.inner.len()));
+                return Err(Chip8Error::OutOfBounds { addr: pos, size: self.size });
             }
         };
         let mut instruction: u16 = data << 8;
 
         let pos = pos + 1;
-        data = match self.inner.get(pos) {
+        data = match self.bytes().get(pos) {
             Some(d) => *d as u16,
             None => {
-                return Err(format!("index {pos} is out of bounds, memory size is {}", self.inner.len()));
+                return Err(Chip8Error::OutOfBounds { addr: pos, size: self.size });
             }
         };
 
@@ -51,329 +194,4364 @@ impl Memory {
         Ok(instruction)
     }
 
-    fn load(&mut self, pos: u16, data: &[u8]) -> Result<(), String> {
-        if pos + data.len() as u16 > self.inner.len() as u16 {
-            return Err(format!("data {} does not fit into memory {} at {}", data.len(), self.inner.len(), pos));
-        }
+    fn load(&mut self, pos: u16, data: &[u8]) -> Result<(), Chip8Error> {
+        let begin = pos as usize;
+        let end = match begin.checked_add(data.len()) {
+            Some(end) if end <= self.size => end,
+            _ => {
+                return Err(Chip8Error::DoesNotFit { len: data.len(), addr: begin, size: self.size });
+            }
+        };
 
-        let range = (pos as usize)..(pos as usize + data.len());
-        self.inner[range].copy_from_slice(&data);
+        self.bytes_mut()[begin..end].copy_from_slice(data);
 
         Ok(())
     }
 }
 
+#[derive(Clone)]
 struct Display {
-    inner: [bool; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+    /// The color index 0-3 of every pixel, kept in sync with `planes`.
+    inner: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+    planes: [[bool; DISPLAY_WIDTH * DISPLAY_HEIGHT]; PLANE_COUNT],
+    /// Pixels changed since the last `take_dirty`.
+    dirty: [bool; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+    /// Set by any pixel change, `Chip8::update` resets it after notifying the display callback.
+    changed: bool,
+    /// Cleared while fast-forwarding, `dirty` is then rebuilt for the whole screen.
+    track_dirty: bool,
+    width: usize,
+    height: usize,
+    brightness: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+    /// Lets pixels that turn off fade out over a few frames instead of going dark instantly.
+    fade: bool,
 }
 
 impl Display {
-    fn draw(&mut self, x: usize, y: usize, flip: bool) -> Result<bool, String> {
-        let pos = x + y * DISPLAY_WIDTH;
-        if pos > DISPLAY_WIDTH * DISPLAY_HEIGHT {
-            return Err(format!("{x}:{y} is out of bounds for the display of size {}", DISPLAY_WIDTH * DISPLAY_HEIGHT));
+    fn new() -> Self {
+        Self {
+            inner: [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            planes: [[false; DISPLAY_WIDTH * DISPLAY_HEIGHT]; PLANE_COUNT],
+            dirty: [false; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            changed: false,
+            track_dirty: true,
+            width: LOWRES_WIDTH,
+            height: LOWRES_HEIGHT,
+            brightness: [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            fade: false,
         }
-        let old = self.inner[pos];
-        self.inner[pos] = self.inner[pos] != flip;
-        Ok(old == true && self.inner[pos] == false)
     }
 
-    fn clear(&mut self) {
-        self.inner = [false; DISPLAY_WIDTH * DISPLAY_HEIGHT]
+    fn size(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn high_res(&self) -> bool {
+        self.width == DISPLAY_WIDTH
+    }
+
+    fn pixels(&self) -> &[u8] {
+        &self.inner[..self.size()]
+    }
+
+    fn draw(&mut self, plane: usize, x: usize, y: usize, flip: bool) -> Result<bool, Chip8Error> {
+        if x >= self.width || y >= self.height {
+            return Err(Chip8Error::PixelOutOfBounds { x, y });
+        }
+        let pos = x + y * self.width;
+        let old = self.planes[plane][pos];
+        if flip {
+            self.set_pixel(plane, pos, !old);
+        }
+        Ok(old && flip)
+    }
+
+    fn clear(&mut self, mask: u8) {
+        for plane in selected_planes(mask) {
+            for pos in 0..self.size() {
+                self.set_pixel(plane, pos, false);
+            }
+        }
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.inner = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        self.planes = [[false; DISPLAY_WIDTH * DISPLAY_HEIGHT]; PLANE_COUNT];
+        self.brightness = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        self.width = width;
+        self.height = height;
+        self.dirty = [false; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        self.mark_all_dirty();
+        self.changed = true;
+    }
+
+    fn mark_all_dirty(&mut self) {
+        let size = self.size();
+        self.dirty[..size].fill(true);
+    }
+
+    fn scroll(&mut self, mask: u8, dx: isize, dy: isize) {
+        let (width, height) = (self.width as isize, self.height as isize);
+        for plane in selected_planes(mask) {
+            let mut scrolled = [false; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+            for y in 0..height {
+                for x in 0..width {
+                    let source_x = x - dx;
+                    let source_y = y - dy;
+                    if (0..width).contains(&source_x) && (0..height).contains(&source_y) {
+                        scrolled[(x + y * width) as usize] = self.planes[plane][(source_x + source_y * width) as usize];
+                    }
+                }
+            }
+
+            for (pos, on) in scrolled[..self.size()].iter().enumerate() {
+                self.set_pixel(plane, pos, *on);
+            }
+        }
+    }
+
+    fn set_pixel(&mut self, plane: usize, pos: usize, on: bool) {
+        if self.planes[plane][pos] == on {
+            return;
+        }
+        self.planes[plane][pos] = on;
+        self.inner[pos] ^= 1 << plane;
+        if self.track_dirty {
+            self.dirty[pos] = true;
+        }
+        self.changed = true;
+        self.sync_brightness(pos);
+    }
+
+    fn sync_brightness(&mut self, pos: usize) {
+        if self.inner[pos] != 0 {
+            self.brightness[pos] = u8::MAX;
+        } else if !self.fade {
+            self.brightness[pos] = 0;
+        }
+    }
+
+    fn sync_screen_brightness(&mut self) {
+        (0..self.size()).for_each(|pos| self.sync_brightness(pos));
+    }
+
+    fn fade_out(&mut self) {
+        let size = self.size();
+        for (brightness, color) in self.brightness[..size].iter_mut().zip(self.inner.iter()) {
+            if *color == 0 {
+                *brightness = brightness.saturating_sub(FADE_STEP);
+            }
+        }
     }
+
+    /// Packs every plane into 8 pixels per byte for a rewind snapshot.
+    #[cfg(feature = "alloc")]
+    fn packed_planes(&self) -> [[u8; DISPLAY_WIDTH * DISPLAY_HEIGHT / 8]; PLANE_COUNT] {
+        let mut packed = [[0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT / 8]; PLANE_COUNT];
+        for (bytes, plane) in packed.iter_mut().zip(self.planes.iter()) {
+            for (byte, pixels) in bytes.iter_mut().zip(plane[..self.size()].chunks(8)) {
+                *byte = pixels.iter().fold(0, |byte, on| byte << 1 | *on as u8);
+            }
+        }
+        packed
+    }
+
+    #[cfg(feature = "alloc")]
+    fn restore_planes(&mut self, width: usize, height: usize, packed: &[[u8; DISPLAY_WIDTH * DISPLAY_HEIGHT / 8]; PLANE_COUNT]) {
+        self.set_resolution(width, height);
+        for (plane, bytes) in packed.iter().enumerate() {
+            for (pos, on) in bytes.iter().flat_map(|byte| get_bits(*byte)).take(self.size()).enumerate() {
+                if on {
+                    self.set_pixel(plane, pos, true);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn take_dirty(&mut self) -> Vec<(usize, usize, bool)> {
+        let dirty = (0..self.size())
+            .filter(|pos| self.dirty[*pos])
+            .map(|pos| (pos % self.width, pos / self.width, self.inner[pos] != 0))
+            .collect();
+        self.dirty = [false; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        dirty
+    }
+}
+
+fn selected_planes(mask: u8) -> impl Iterator<Item = usize> {
+    (0..PLANE_COUNT).filter(move |plane| mask & (1 << plane) != 0)
 }
 
+#[derive(Clone)]
 struct Stack {
-    inner: Vec<u16>,
+    inner: [u16; STACK_DEPTH],
+    len: usize,
+}
+
+impl Stack {
+    fn new() -> Self {
+        Self { inner: [0u16; STACK_DEPTH], len: 0 }
+    }
+
+    fn as_slice(&self) -> &[u16] {
+        &self.inner[..self.len]
+    }
+
+    fn push(&mut self, address: u16) -> Result<(), Chip8Error> {
+        let slot = self.inner.get_mut(self.len).ok_or(Chip8Error::StackOverflow)?;
+        *slot = address;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<u16, Chip8Error> {
+        self.len = self.len.checked_sub(1).ok_or(Chip8Error::StackUnderflow)?;
+        Ok(self.inner[self.len])
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
 }
 
+#[derive(Clone)]
 struct Timer {
     inner: u8,
 }
 
-#[derive(Default)]
-struct InputBuffer {
-    inner: Vec<(char, bool)>,
+impl Timer {
+    fn tick(&mut self) {
+        self.inner = self.inner.saturating_sub(1);
+    }
+}
+
+#[derive(Clone)]
+struct Rng {
+    inner: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero, so replace it with an arbitrary non-zero state
+        let inner = if seed == 0 { 0x2545_F491_4F6C_DD1D } else { seed };
+        Self { inner }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        let mut x = self.inner;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.inner = x;
+        (x >> 32) as u8
+    }
+}
+
+#[derive(Clone)]
+struct Keypad {
+    inner: [bool; KEY_COUNT],
+    previous: [bool; KEY_COUNT],
+    /// Keys that went down since the last snapshot, so a press and release between two
+    /// snapshots still counts as a release.
+    pressed: [bool; KEY_COUNT],
+}
+
+impl Keypad {
+    fn new() -> Self {
+        Self { inner: [false; KEY_COUNT], previous: [false; KEY_COUNT], pressed: [false; KEY_COUNT] }
+    }
+
+    fn set(&mut self, key: u8, down: bool) {
+        if let Some(state) = self.inner.get_mut(key as usize) {
+            *state = down;
+            self.pressed[key as usize] |= down;
+        }
+    }
+
+    fn is_down(&self, key: u8) -> bool {
+        self.inner.get(key as usize).copied().unwrap_or(false)
+    }
+
+    fn just_pressed(&self, key: u8) -> bool {
+        self.is_down(key) && !self.previous.get(key as usize).copied().unwrap_or(true)
+    }
+
+    /// Returns the lowest key `0x0`-`0xF` that is down.
+    fn first_down(&self) -> Option<u8> {
+        (0..KEY_COUNT).find(|key| self.inner[*key]).map(|key| key as u8)
+    }
+
+    /// Returns a key `0x0`-`0xF` that was down on the last snapshot or went down since but is up
+    /// now and forgets its release.
+    fn take_released(&mut self) -> Option<u8> {
+        let key = (0..KEY_COUNT).find(|key| (self.previous[*key] || self.pressed[*key]) && !self.inner[*key])?;
+        self.previous[key] = false;
+        self.pressed[key] = false;
+        Some(key as u8)
+    }
+
+    fn advance(&mut self) {
+        // a key tapped since the last snapshot stays visible as released for one more snapshot
+        for key in 0..KEY_COUNT {
+            self.previous[key] = self.inner[key] || self.pressed[key];
+        }
+        self.pressed = [false; KEY_COUNT];
+    }
+}
+
+/// Machine state captured before every `update` so that `Chip8::rewind` can restore it. Host
+/// settings like fading or dirty tracking are not part of it and survive a rewind.
+#[cfg(feature = "alloc")]
+struct Snapshot {
+    /// The active memory size worth of bytes.
+    memory: Vec<u8>,
+    planes: [[u8; DISPLAY_WIDTH * DISPLAY_HEIGHT / 8]; PLANE_COUNT],
+    resolution: (usize, usize),
+    keypad: Keypad,
+    /// The queued key events the frame applied, `rewind` puts them back into the queue.
+    input: Vec<(u8, bool)>,
+    program_counter: u16,
+    index_register: u16,
+    stack: Stack,
+    delay_timer: Timer,
+    sound_timer: Timer,
+    variable_registers: [u8; VARIABLE_REGISTER_SIZE],
+    rpl_flags: [u8; RPL_FLAG_COUNT],
+    plane: u8,
+    rng: Rng,
+    halted: bool,
+    frame_counter: usize,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct State {
+    memory: Vec<u8>,
+    display: Vec<u8>,
+    high_res: bool,
+    plane: u8,
+    program_counter: u16,
+    index_register: u16,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    variable_registers: [u8; VARIABLE_REGISTER_SIZE],
+    rpl_flags: [u8; RPL_FLAG_COUNT],
+    rng: u64,
+    halted: bool,
+    program_start: u16,
+    program_len: usize,
+    frame_counter: usize,
+}
+
+/// Behavior that differs between CHIP-8 variants, the default matches modern interpreters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    /// The COSMAC VIP shifted VY into VX for 8XY6/8XYE, later interpreters shift VX in place.
+    pub shift_uses_vy: bool,
+    /// The COSMAC VIP left I pointing past the last register after FX55/FX65,
+    /// modern interpreters leave it unchanged.
+    pub memory_increments_index: bool,
+    /// SUPER-CHIP reads BNNN as BXNN and jumps to XNN + VX instead of NNN + V0.
+    pub jump_offset_uses_vx: bool,
+    /// The Amiga interpreter sets VF when FX1E moves I past 0x0FFF, which
+    /// Spacefight 2091! relies on.
+    pub index_overflow_sets_flag: bool,
+    /// Some games expect sprites to wrap around to the left edge instead of being
+    /// clipped at the right edge.
+    pub wrap_x: bool,
+    /// Like `wrap_x` for the bottom edge, some platforms wrap only one axis.
+    pub wrap_y: bool,
+    /// The COSMAC VIP waited for the display interrupt before drawing, so at most one
+    /// sprite is drawn per frame and `update` stops after a draw.
+    pub display_wait: bool,
+    /// Legacy FX0A without key-repeat suppression: it completes as soon as any key is down, so a
+    /// held key is reported again on every FX0A. Without it FX0A only completes once a key goes
+    /// down and up again and consumes that release, which keeps menu ROMs from seeing one press
+    /// as many.
+    pub wait_key_repeats: bool,
+    /// SUPER-CHIP only scrolls in high-res mode and ignores 00CN/00FB/00FC in low-res,
+    /// XO-CHIP scrolls either resolution.
+    pub scroll_low_res: bool,
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            memory_increments_index: true,
+            display_wait: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn schip() -> Self {
+        Self {
+            jump_offset_uses_vx: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn xo_chip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            memory_increments_index: true,
+            wrap_x: true,
+            wrap_y: true,
+            scroll_low_res: true,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent {
+    Execute { pc: u16, opcode: u16, instruction: Instruction },
+    /// FX55 or FX33 wrote into the loaded program, which is rarely intended.
+    SelfModify { addr: u16 },
+    /// An opcode that does not decode was skipped in lenient mode.
+    UnknownInstruction { pc: u16, opcode: u16 },
+}
+
+impl core::fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TraceEvent::Execute { pc, opcode, instruction } => write!(f, "{:#06x}:   {:#06x}   -   {}", pc, opcode, instruction),
+            TraceEvent::SelfModify { addr } => write!(f, "{:#06x}:   program modified itself", addr),
+            TraceEvent::UnknownInstruction { pc, opcode } => write!(f, "{:#06x}:   {:#06x}   -   skipped unknown instruction", pc, opcode),
+        }
+    }
+}
+
+/// What happened during `Chip8::step`, or the last step of an `update`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Continued,
+    /// The program jumped to its own address and will not make progress.
+    Halted,
+    /// FX0A is waiting for a key to be released.
+    AwaitingKey,
+    /// The instruction at this address was not executed because it has a breakpoint.
+    Breakpoint(u16),
+}
+
+/// Logical game inputs that `Chip8::on_direction` translates through the `KeyMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Action,
+}
+
+/// The hex keys a ROM expects for each `Direction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyMap {
+    pub up: u8,
+    pub down: u8,
+    pub left: u8,
+    pub right: u8,
+    pub action: u8,
+}
+
+impl KeyMap {
+    /// 2/8/4/6 with 5 in the middle, the layout most ROMs use.
+    pub fn numpad() -> Self {
+        Self { up: 0x2, down: 0x8, left: 0x4, right: 0x6, action: 0x5 }
+    }
+
+    /// 5/8/7/9 with 6, the keys under W/S/A/D and E on the usual QWERTY keypad mapping.
+    pub fn wasd() -> Self {
+        Self { up: 0x5, down: 0x8, left: 0x7, right: 0x9, action: 0x6 }
+    }
+
+    pub fn key(&self, direction: Direction) -> u8 {
+        match direction {
+            Direction::Up => self.up,
+            Direction::Down => self.down,
+            Direction::Left => self.left,
+            Direction::Right => self.right,
+            Direction::Action => self.action,
+        }
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::numpad()
+    }
+}
+
+pub type Rgb = (u8, u8, u8);
+
+/// Receives the pixels of `Chip8::screen` whenever an `update` changed them.
+#[cfg(feature = "alloc")]
+pub type DisplayCallback = Box<dyn FnMut(&[u8])>;
+
+/// Colors used by `Chip8::render`, indexed by the XO-CHIP color index of a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// Pixels that are off on every plane.
+    pub bg: Rgb,
+    /// Pixels that are only on in the first plane, the only color plain CHIP-8 programs use.
+    pub fg: Rgb,
+    /// Pixels that are only on in the second plane.
+    pub fg2: Rgb,
+    /// Pixels that are on in both planes.
+    pub blend: Rgb,
+}
+
+impl Palette {
+    pub fn classic() -> Self {
+        Self { bg: (0, 0, 0), fg: (255, 255, 255), fg2: (170, 170, 170), blend: (85, 85, 85) }
+    }
+
+    pub fn amber() -> Self {
+        Self { bg: (40, 24, 0), fg: (255, 176, 0), fg2: (170, 102, 0), blend: (255, 224, 128) }
+    }
+
+    pub fn color(&self, index: u8) -> Rgb {
+        match index & ALL_PLANES {
+            0 => self.bg,
+            1 => self.fg,
+            2 => self.fg2,
+            _ => self.blend,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::classic()
+    }
 }
 
 pub trait Screen {
-    fn draw(&mut self, x: usize, y: usize, draw: bool);
-    fn clear(&mut self);
+    fn draw(&mut self, x: usize, y: usize, color: Rgb);
+    fn clear(&mut self, color: Rgb);
+}
+
+pub trait Audio {
+    fn set_playing(&mut self, playing: bool);
 }
 
 pub struct Chip8 {
     memory: Memory,
     display: Display,
-    input: InputBuffer,
+    keypad: Keypad,
+    keymap: KeyMap,
+    #[cfg(feature = "alloc")]
+    input_queue: BTreeMap<usize, Vec<(u8, bool)>>,
+    frame_counter: usize,
+    cycles: u64,
+    last_draw_collisions: u32,
+    clock_hz: u32,
+    /// Nanoseconds times Hz left over from the last `update_for` that did not add up to a whole cycle.
+    cycle_carry: u128,
+    /// Like `cycle_carry` for the 60Hz timers driven by `service`.
+    timer_carry: u128,
+    palette: Palette,
+    #[cfg(feature = "alloc")]
+    breakpoints: BTreeSet<u16>,
+    /// Set to the address of a reported breakpoint so that the next step executes the instruction under it.
+    #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+    resume_breakpoint: Option<u16>,
     program_counter: u16,
     index_register: u16,
     stack: Stack,
     delay_timer: Timer,
     sound_timer: Timer,
     variable_registers: [u8; VARIABLE_REGISTER_SIZE],
+    rpl_flags: [u8; RPL_FLAG_COUNT],
+    ticks: usize,
+    #[cfg(feature = "alloc")]
+    trace: Option<Box<dyn FnMut(TraceEvent)>>,
+    #[cfg(feature = "alloc")]
+    display_callback: Option<DisplayCallback>,
+    #[cfg(feature = "alloc")]
+    trace_history: Option<VecDeque<(u16, u16)>>,
+    #[cfg(feature = "alloc")]
+    rewind_buffer: Option<VecDeque<Snapshot>>,
+    #[cfg(feature = "alloc")]
+    rewind_capacity: usize,
+    #[cfg(feature = "alloc")]
+    opcode_stats: Option<BTreeMap<&'static str, u64>>,
+    halted: bool,
+    paused: bool,
+    lenient: bool,
+    fast_forwarding: bool,
+    waiting_for_vblank: bool,
+    program_start: u16,
+    program_len: usize,
+    /// Bit mask of the XO-CHIP planes that draw, clear and scroll instructions affect.
+    plane: u8,
+    rng: Rng,
+    quirks: Quirks,
+}
+
+/// Collects the configuration of a `Chip8`, unset options keep their defaults.
+#[derive(Debug, Clone, Default)]
+pub struct Chip8Builder {
     ticks: usize,
     debug: bool,
+    seed: Option<u64>,
+    quirks: Quirks,
+    memory_size: Option<usize>,
+    clock_hz: Option<u32>,
+    lenient: bool,
+}
+
+impl Chip8Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many instructions `update` executes, 0 is treated as 1.
+    pub fn ticks(mut self, ticks: usize) -> Self {
+        self.ticks = ticks;
+        self
+    }
+
+    /// Prints every executed instruction to stdout. This needs the `std` feature, without it
+    /// the flag is ignored and `Chip8::set_trace_callback` can route the events instead.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Seeds the random number generator, by default it is seeded from the system time.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Sets the RAM size in bytes, `RAM_SIZE` by default and `XO_CHIP_RAM_SIZE` at most.
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = Some(size);
+        self
+    }
+
+    /// Sets the instruction frequency used by `Chip8::update_for`.
+    pub fn clock_hz(mut self, hz: u32) -> Self {
+        self.clock_hz = Some(hz);
+        self
+    }
+
+    /// Skips unknown opcodes instead of failing, see `Chip8::set_lenient`.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    pub fn build(self) -> Result<Chip8, Chip8Error> {
+        let seed = self.seed.unwrap_or_else(default_seed);
+        let memory = Memory::new(self.memory_size.unwrap_or(RAM_SIZE))?;
+        let mut chip = Chip8::create(self.ticks, self.debug, seed, self.quirks, memory)?;
+        if let Some(hz) = self.clock_hz {
+            chip.set_clock_hz(hz);
+        }
+        chip.set_lenient(self.lenient);
+        Ok(chip)
+    }
+}
+
+#[cfg(feature = "std")]
+fn default_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Without a clock every run uses the same sequence unless a seed is given.
+#[cfg(not(feature = "std"))]
+fn default_seed() -> u64 {
+    0
 }
 
 impl Chip8 {
-    pub fn new(ticks: usize, debug: bool) -> Result<Self, String> {
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder::new()
+    }
+
+    /// Creates an interpreter that executes `ticks` instructions per `update`,
+    /// `debug` prints every instruction like `Chip8Builder::debug` and needs `std`.
+    pub fn new(ticks: usize, debug: bool) -> Result<Self, Chip8Error> {
+        Self::builder().ticks(ticks).debug(debug).build()
+    }
+
+    pub fn new_with_seed(ticks: usize, debug: bool, seed: u64) -> Result<Self, Chip8Error> {
+        Self::builder().ticks(ticks).debug(debug).seed(seed).build()
+    }
+
+    pub fn new_with_quirks(ticks: usize, debug: bool, quirks: Quirks) -> Result<Self, Chip8Error> {
+        Self::builder().ticks(ticks).debug(debug).quirks(quirks).build()
+    }
+
+    fn create(ticks: usize, debug: bool, seed: u64, quirks: Quirks, memory: Memory) -> Result<Self, Chip8Error> {
         let mut chip = Self {
-            memory: Memory { inner: [0u8; RAM_SIZE] },
-            display: Display { inner: [false; DISPLAY_WIDTH * DISPLAY_HEIGHT] },
-            input: InputBuffer::default(),
+            memory,
+            display: Display::new(),
+            keypad: Keypad::new(),
+            keymap: KeyMap::default(),
+            #[cfg(feature = "alloc")]
+            input_queue: BTreeMap::new(),
+            frame_counter: 0,
+            cycles: 0,
+            last_draw_collisions: 0,
+            clock_hz: DEFAULT_CLOCK_HZ,
+            cycle_carry: 0,
+            timer_carry: 0,
+            palette: Palette::default(),
+            #[cfg(feature = "alloc")]
+            breakpoints: BTreeSet::new(),
+            resume_breakpoint: None,
             program_counter: 0,
             index_register: 0,
-            stack: Stack { inner: Vec::new() },
+            stack: Stack::new(),
             delay_timer: Timer { inner: 0 },
             sound_timer: Timer { inner: 0 },
             variable_registers: [0u8; VARIABLE_REGISTER_SIZE],
-            ticks,
-            debug,
+            rpl_flags: [0u8; RPL_FLAG_COUNT],
+            ticks: ticks.max(1),
+            #[cfg(feature = "alloc")]
+            trace: None,
+            #[cfg(feature = "alloc")]
+            display_callback: None,
+            #[cfg(feature = "alloc")]
+            trace_history: None,
+            #[cfg(feature = "alloc")]
+            rewind_buffer: None,
+            #[cfg(feature = "alloc")]
+            rewind_capacity: 0,
+            #[cfg(feature = "alloc")]
+            opcode_stats: None,
+            halted: false,
+            paused: false,
+            lenient: false,
+            fast_forwarding: false,
+            waiting_for_vblank: false,
+            program_start: PROGRAM_START,
+            program_len: 0,
+            plane: 1,
+            rng: Rng::new(seed),
+            quirks,
         };
 
-        match chip.memory.load(0x050, &FONT) {
-            Ok(_) => Ok(chip),
-            Err(err) => Err(format!("could not load font into memory: {err}"))
+        // there is nothing to print to without std, see `Chip8Builder::debug`
+        #[cfg(feature = "std")]
+        if debug {
+            chip.set_trace_callback(Box::new(|event| println!("{event}")));
         }
+        #[cfg(not(feature = "std"))]
+        let _ = debug;
+        chip.memory.load(FONT_BASE, &FONT)?;
+        chip.memory.load(BIG_FONT_ADDRESS, &BIG_FONT)?;
+        Ok(chip)
     }
 
-    pub fn screen(&self) -> &[bool] {
-        self.display.inner.as_slice()
+    /// Sets how many instructions `update` executes, 0 is treated as 1.
+    pub fn set_ticks(&mut self, ticks: usize) {
+        self.ticks = ticks.max(1);
     }
 
-    pub fn on_input(&mut self, input: char, down: bool) {
-        self.input.inner.push((input, down))
+    pub fn ticks(&self) -> usize {
+        self.ticks
     }
 
-    pub fn load_program(&mut self, data: &[u8]) -> Result<(), String> {
-        if let Err(err) = self.memory.load(0x200, data) {
-            return Err(format!("could not load program: {err}"));
-        }
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
 
-        self.program_counter = 0x200;
-        Ok(())
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
-    pub fn update(&mut self) -> Result<(), String> {
-        for _ in 0..self.ticks {
-            if self.debug {
-                println!("State:   PC: {} I: {} registers: {:?}", self.program_counter, self.index_register, self.variable_registers);
-            }
+    /// Returns the XO-CHIP color index 0-3 of every pixel of the active resolution row by row,
+    /// bit 0 is the first plane and bit 1 the second, so 0 is dark.
+    pub fn screen(&self) -> &[u8] {
+        self.display.pixels()
+    }
 
-            let encoded_instruction = self.fetch()?;
-            let instruction = Instruction::try_from(encoded_instruction)?;
-            if self.debug {
-                println!("{:#06x}   -   {}", encoded_instruction, instruction);
-            }
-            self.execute(instruction)?;
+    /// Returns a stable FNV-1a fingerprint of `screen()` for comparing against golden values.
+    pub fn display_hash(&self) -> u64 {
+        self.display.pixels().iter().fold(0xcbf2_9ce4_8422_2325, |hash, color| {
+            (hash ^ *color as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Packs `screen()` into 8 pixels per byte, MSB-first per row. In low-res mode only the
+    /// first `LOWRES_WIDTH * LOWRES_HEIGHT / 8` bytes are used.
+    pub fn screen_packed(&self) -> [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT / 8] {
+        let mut packed = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT / 8];
+        for (byte, pixels) in packed.iter_mut().zip(self.display.pixels().chunks(8)) {
+            *byte = pixels.iter().fold(0, |byte, color| byte << 1 | (*color != 0) as u8);
+        }
+        packed
+    }
+
+    /// Packs the active resolution like `screen_packed`, 256 bytes in low-res and 1024 in high-res mode.
+    #[cfg(feature = "alloc")]
+    pub fn display_snapshot(&self) -> Vec<u8> {
+        self.screen_packed()[..self.display.size() / 8].to_vec()
+    }
+
+    /// Replaces the screen with a `display_snapshot`, its length selects the resolution.
+    /// The pixels are restored on the first plane.
+    pub fn restore_display(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let (width, height) = match data.len() * 8 {
+            size if size == LOWRES_WIDTH * LOWRES_HEIGHT => (LOWRES_WIDTH, LOWRES_HEIGHT),
+            size if size == DISPLAY_WIDTH * DISPLAY_HEIGHT => (DISPLAY_WIDTH, DISPLAY_HEIGHT),
+            _ => return Err(Chip8Error::InvalidState),
+        };
+        if (width, height) == self.resolution() {
+            self.display.clear(ALL_PLANES);
+        } else {
+            self.display.set_resolution(width, height);
+        }
+        for (pos, on) in data.iter().flat_map(|byte| get_bits(*byte)).enumerate() {
+            self.display.set_pixel(0, pos, on);
         }
         Ok(())
     }
 
-    fn fetch(&mut self) -> Result<u16, String> {
-        let instruction = self.memory.get_instruction(self.program_counter as usize)?;
-        self.program_counter += 2;
-        Ok(instruction)
+    /// Renders the current frame as a binary PPM (P6) image, white on black, scaled by `scale`.
+    #[cfg(feature = "alloc")]
+    pub fn export_ppm(&self, scale: usize) -> Vec<u8> {
+        self.export_ppm_with_colors(scale, (255, 255, 255), (0, 0, 0))
     }
 
-    fn execute(&mut self, instruction: Instruction) -> Result<(), String> {
-        match instruction {
-            Instruction::ClearScreen => { self.display.clear() }
-            Instruction::Jump(address) => {
-                self.program_counter = address;
-            }
-            Instruction::Call(address) => {
-                self.stack.inner.push(self.program_counter);
-                self.program_counter = address;
-            }
-            Instruction::Return => {
-                let address = self.stack.inner.pop().ok_or("stack is empty")?;
-                self.program_counter = address;
-            }
-            Instruction::SkipEqVal { register, value } => {
-                if self.variable_registers[register] == value {
-                    self.program_counter += 2;
-                }
-            }
-            Instruction::SkipNeVal { register, value } => {
-                if self.variable_registers[register] != value {
-                    self.program_counter += 2;
-                }
-            }
-            Instruction::SkipEqReg { x_register, y_register } => {
-                if self.variable_registers[x_register] == self.variable_registers[y_register] {
-                    self.program_counter += 2;
+    #[cfg(feature = "alloc")]
+    pub fn export_ppm_with_colors(&self, scale: usize, fg: (u8, u8, u8), bg: (u8, u8, u8)) -> Vec<u8> {
+        let (width, height) = (self.display.width * scale, self.display.height * scale);
+        let mut ppm = format!("P6\n{width} {height}\n255\n").into_bytes();
+        ppm.reserve(width * height * 3);
+        for row in self.display.pixels().chunks(self.display.width) {
+            for _ in 0..scale {
+                for color in row {
+                    let (r, g, b) = if *color != 0 { fg } else { bg };
+                    for _ in 0..scale {
+                        ppm.extend_from_slice(&[r, g, b]);
+                    }
                 }
             }
-            Instruction::SkipNeReg { x_register, y_register } => {
-                if self.variable_registers[x_register] != self.variable_registers[y_register] {
-                    self.program_counter += 2;
+        }
+        ppm
+    }
+
+    /// Draws the screen as text, one line per row with `█` for lit and a space for dark pixels.
+    #[cfg(feature = "alloc")]
+    pub fn render_ascii(&self) -> String {
+        let mut text = String::with_capacity(self.display.size() * 3 + self.display.height);
+        for row in self.display.pixels().chunks(self.display.width) {
+            text.extend(row.iter().map(|color| if *color != 0 { '█' } else { ' ' }));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Returns the brightness of every pixel of the active resolution, 255 for pixels that are on.
+    /// With fading enabled pixels that turned off dim a bit on every `tick_timers`.
+    pub fn screen_brightness(&self) -> &[u8] {
+        &self.display.brightness[..self.display.size()]
+    }
+
+    pub fn set_fade(&mut self, enabled: bool) {
+        self.display.fade = enabled;
+        self.display.sync_screen_brightness();
+    }
+
+    /// Returns the active `(width, height)`, 64x32 or 128x64 in high-res mode.
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.display.width, self.display.height)
+    }
+
+    pub fn registers(&self) -> &[u8; VARIABLE_REGISTER_SIZE] {
+        &self.variable_registers
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer.inner
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer.inner
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        self.stack.as_slice()
+    }
+
+    pub fn peek_memory(&self, addr: u16) -> Option<u8> {
+        self.memory.bytes().get(addr as usize).copied()
+    }
+
+    /// Copies the bytes in `range`, which has to lie inside memory.
+    #[cfg(feature = "alloc")]
+    pub fn dump_memory(&self, range: core::ops::Range<u16>) -> Result<Vec<u8>, Chip8Error> {
+        let size = self.memory.size();
+        let bytes = self.memory.bytes().get(range.start as usize..range.end as usize);
+        let bytes = bytes.ok_or(Chip8Error::OutOfBounds { addr: (range.end as usize).saturating_sub(1), size })?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Formats `range` like `hexdump -C`, 16 bytes per line with the address, hex values and ASCII.
+    #[cfg(feature = "alloc")]
+    pub fn hexdump(&self, range: core::ops::Range<u16>) -> Result<String, Chip8Error> {
+        let start = range.start as usize;
+        let bytes = self.dump_memory(range)?;
+        let mut text = String::new();
+        for (i, line) in bytes.chunks(16).enumerate() {
+            text.push_str(&format!("{:04x} ", start + i * 16));
+            for column in 0..16 {
+                if column == 8 {
+                    text.push(' ');
+                }
+                match line.get(column) {
+                    Some(byte) => text.push_str(&format!(" {byte:02x}")),
+                    None => text.push_str("   "),
                 }
             }
-            Instruction::SetRegister { register, value } => { self.variable_registers[register] = value }
-            Instruction::AddRegister { register, value } => { self.variable_registers[register] += value }
-            Instruction::SetIndex(address) => { self.index_register = address }
-            Instruction::Draw { x_register, y_register, count } => {
-                let start_x = (self.variable_registers[x_register] & ((DISPLAY_WIDTH - 1) as u8)) as usize;
-                let start_y = (self.variable_registers[y_register] & ((DISPLAY_HEIGHT - 1) as u8)) as usize;
-                self.variable_registers[FLAG_REGISTER] = 0;
-
-                let begin = self.index_register as usize;
-                let end = (self.index_register + count as u16) as usize;
-                let mut y = start_y;
-                for i in begin..end {
-                    let sprite_row = self.memory.inner[i];
-                    let bits = get_bits(sprite_row);
-
-                    let mut x = start_x;
-                    for bit in bits {
-                        let turned_off = self.display.draw(x, y, bit)?;
-                        if turned_off {
-                            self.variable_registers[FLAG_REGISTER] = 1;
-                        }
+            let ascii: String = line.iter().map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }).collect();
+            text.push_str(&format!("  |{ascii}|\n"));
+        }
+        Ok(text)
+    }
 
-                        x += 1;
-                        if x >= DISPLAY_WIDTH - 1 {
-                            break;
-                        }
-                    }
+    pub fn poke_memory(&mut self, addr: u16, value: u8) -> Result<(), Chip8Error> {
+        let size = self.memory.size();
+        let byte = self.memory.bytes_mut().get_mut(addr as usize).ok_or(Chip8Error::OutOfBounds { addr: addr as usize, size })?;
+        *byte = value;
+        Ok(())
+    }
 
-                    y += 1;
-                    if x >= DISPLAY_WIDTH - 1 && y >= DISPLAY_HEIGHT - 1 {
-                        break;
-                    }
-                }
+    /// Sets V0-VF, `index` is the register number 0-15.
+    pub fn set_register(&mut self, index: usize, value: u8) -> Result<(), Chip8Error> {
+        let register = self.variable_registers.get_mut(index).ok_or(Chip8Error::InvalidRegister(index.min(u8::MAX as usize) as u8))?;
+        *register = value;
+        Ok(())
+    }
+
+    pub fn set_index(&mut self, addr: u16) {
+        self.index_register = addr;
+    }
+
+    /// Clears the screen to the background color and draws every pixel that is currently on.
+    pub fn render(&self, screen: &mut dyn Screen, palette: &Palette) {
+        screen.clear(palette.bg);
+        let width = self.display.width;
+        for pos in 0..self.display.size() {
+            let color = self.display.inner[pos];
+            if color != 0 {
+                screen.draw(pos % width, pos / width, palette.color(color));
             }
         }
+    }
+
+    /// Returns the pixels changed since the last call as `(x, y, on)` and
+    /// resets the dirty state.
+    #[cfg(feature = "alloc")]
+    pub fn take_dirty(&mut self) -> Vec<(usize, usize, bool)> {
+        self.display.take_dirty()
+    }
+
+    /// Updates the state of the hex keypad key `0x0`-`0xF`, other values are ignored.
+    pub fn on_input(&mut self, key: u8, down: bool) {
+        self.keypad.set(key, down);
+    }
+
+    /// Sets the hex keys `on_direction` presses, ROMs differ in the layout they expect.
+    pub fn set_keymap(&mut self, keymap: KeyMap) {
+        self.keymap = keymap;
+    }
+
+    pub fn keymap(&self) -> KeyMap {
+        self.keymap
+    }
+
+    /// Updates the hex key that the keymap assigns to `direction`.
+    pub fn on_direction(&mut self, direction: Direction, down: bool) {
+        self.on_input(self.keymap.key(direction), down);
+    }
+
+    /// Returns true if `key` is down now but was up when the timers last ticked.
+    pub fn just_pressed(&self, key: u8) -> bool {
+        self.keypad.just_pressed(key)
+    }
+
+    /// Decrements the delay and sound timers, the host should call this at 60Hz
+    /// independently of how many instructions `update` executes. It also snapshots the keypad
+    /// for `just_pressed` and the release detection of FX0A.
+    pub fn tick_timers(&mut self) {
+        if self.paused {
+            return;
+        }
+        self.delay_timer.tick();
+        self.sound_timer.tick();
+        self.keypad.advance();
+        if self.display.fade {
+            self.display.fade_out();
+        }
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer.inner > 0
+    }
+
+    /// Sets the colors `service` renders with.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Advances the machine by `dt` in one call for a frontend loop: runs `update_for`, ticks the
+    /// timers at 60Hz, then renders the screen and sets the audio state.
+    pub fn service(&mut self, dt: Duration, screen: &mut dyn Screen, audio: &mut dyn Audio) -> Result<StepOutcome, Chip8Error> {
+        let outcome = self.update_for(dt)?;
+
+        let budget = dt.as_nanos() * TIMER_HZ + self.timer_carry;
+        self.timer_carry = budget % 1_000_000_000;
+        for _ in 0..budget / 1_000_000_000 {
+            self.tick_timers();
+        }
+
+        self.render(screen, &self.palette);
+        self.render_audio(audio);
+        Ok(outcome)
+    }
+
+    /// Starts or stops the beep of `audio` depending on the sound timer.
+    pub fn render_audio(&self, audio: &mut dyn Audio) {
+        audio.set_playing(self.sound_active());
+    }
+
+    /// Schedules a key event that `update` applies before executing the given frame,
+    /// which makes runs with recorded input reproducible.
+    #[cfg(feature = "alloc")]
+    pub fn queue_input(&mut self, frame: usize, key: u8, down: bool) {
+        self.input_queue.entry(frame).or_default().push((key, down));
+    }
+
+    /// Returns how many times `update` has run.
+    pub fn frame_counter(&self) -> usize {
+        self.frame_counter
+    }
+
+    /// Returns the `FONT_LEN` font bytes at `FONT_BASE` as currently stored in memory.
+    pub fn font_bytes(&self) -> &[u8] {
+        let base = FONT_BASE as usize;
+        &self.memory.bytes()[base..base + FONT_LEN]
+    }
+
+    /// Replaces the 4x5 hex digits FX29 points at, `font` holds 5 bytes for each of the 16 characters.
+    pub fn set_font(&mut self, font: &[u8]) -> Result<(), Chip8Error> {
+        if font.len() != FONT_LEN {
+            return Err(Chip8Error::InvalidFont { len: font.len(), expected: FONT_LEN });
+        }
+        self.memory.load(FONT_BASE, font)
+    }
+
+    pub fn load_program(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.load_program_at(PROGRAM_START, data)
+    }
+
+    /// Reads a ROM from disk and loads it like `load_program`.
+    #[cfg(feature = "std")]
+    pub fn load_program_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Chip8Error> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|err| Chip8Error::Io(format!("{}: {err}", path.display())))?;
+        self.load_program(&data)
+    }
+
+    /// Loads a program that expects to start at `origin`, e.g. 0x600 for the ETI-660. The origin
+    /// must be even and must not overlap the interpreter area below `PROGRAM_START`.
+    pub fn load_program_at(&mut self, origin: u16, data: &[u8]) -> Result<(), Chip8Error> {
+        if origin < PROGRAM_START || origin & 1 != 0 {
+            return Err(Chip8Error::InvalidOrigin(origin));
+        }
+        let size = self.memory.size();
+        if origin as usize >= size {
+            return Err(Chip8Error::OutOfBounds { addr: origin as usize, size });
+        }
+        let max = size - origin as usize;
+        if data.is_empty() {
+            return Err(Chip8Error::EmptyProgram);
+        }
+        if data.len() > max {
+            return Err(Chip8Error::ProgramTooLarge { size: data.len(), max });
+        }
+        // a previous, longer program must not leave stale bytes behind the new one
+        self.memory.clear_from(origin as usize);
+        self.memory.load(origin, data)?;
+        self.program_start = origin;
+        self.program_len = data.len();
+
+        self.program_counter = origin;
+        self.resume_breakpoint = None;
+        self.halted = false;
         Ok(())
     }
-}
 
-fn get_bits(byte: u8) -> [bool; 8] {
-    let mut bits = [false; 8];
-    for i in 0..8 {
-        let bit = byte >> i & 1;
-        bits[7 - i] = bit == 1;
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self) -> Result<Vec<u8>, Chip8Error> {
+        let state = State {
+            memory: self.memory.bytes().to_vec(),
+            display: self.screen().to_vec(),
+            high_res: self.display.high_res(),
+            plane: self.plane,
+            program_counter: self.program_counter,
+            index_register: self.index_register,
+            stack: self.stack.as_slice().to_vec(),
+            delay_timer: self.delay_timer.inner,
+            sound_timer: self.sound_timer.inner,
+            variable_registers: self.variable_registers,
+            rpl_flags: self.rpl_flags,
+            rng: self.rng.inner,
+            halted: self.halted,
+            program_start: self.program_start,
+            program_len: self.program_len,
+            frame_counter: self.frame_counter,
+        };
+        bincode::serialize(&state).map_err(|_| Chip8Error::StateNotSerializable)
     }
 
-    bits
-}
+    #[cfg(feature = "serde")]
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let state: State = bincode::deserialize(data).map_err(|_| Chip8Error::InvalidState)?;
+        let size = if state.high_res { DISPLAY_WIDTH * DISPLAY_HEIGHT } else { LOWRES_WIDTH * LOWRES_HEIGHT };
+        if state.memory.len() != self.memory.size() || state.display.len() != size || state.display.iter().any(|color| *color > ALL_PLANES) || state.stack.len() > STACK_DEPTH {
+            return Err(Chip8Error::InvalidState);
+        }
+        if state.program_start as usize + state.program_len > state.memory.len() {
+            return Err(Chip8Error::InvalidState);
+        }
 
-fn nth_nibble(instruction: u16, nth: u8) -> Result<u8, String> {
-    match nth {
-        1 => Ok(0b1111 & (instruction >> 12) as u8),
-        2 => Ok(0b1111 & (instruction >> 8) as u8),
-        3 => Ok(0b1111 & (instruction >> 4) as u8),
-        4 => Ok(0b1111 & instruction as u8),
-        _ => {
-            return Err(format!("valid range for nibbles are 1-4 but got {nth}"));
+        self.memory.bytes_mut().copy_from_slice(&state.memory);
+        if state.high_res {
+            self.display.set_resolution(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        } else {
+            self.display.set_resolution(LOWRES_WIDTH, LOWRES_HEIGHT);
+        }
+        for (pos, color) in state.display.iter().enumerate() {
+            for plane in selected_planes(*color) {
+                self.display.set_pixel(plane, pos, true);
+            }
         }
+        self.plane = state.plane & ALL_PLANES;
+        self.program_counter = state.program_counter;
+        self.index_register = state.index_register;
+        self.stack.clear();
+        for address in state.stack {
+            self.stack.push(address)?;
+        }
+        self.delay_timer.inner = state.delay_timer;
+        self.sound_timer.inner = state.sound_timer;
+        self.variable_registers = state.variable_registers;
+        self.rpl_flags = state.rpl_flags;
+        self.rng = Rng::new(state.rng);
+        self.halted = state.halted;
+        self.program_start = state.program_start;
+        self.program_len = state.program_len;
+        self.frame_counter = state.frame_counter;
+        // whatever the machine was doing before the load does not apply to the restored state
+        self.resume_breakpoint = None;
+        self.waiting_for_vblank = false;
+        Ok(())
     }
-}
 
-enum Instruction {
-    ClearScreen,
-    Jump(u16),
-    Call(u16),
-    Return,
-    SkipEqVal {
-        register: usize,
-        value: u8,
-    },
-    SkipNeVal {
-        register: usize,
-        value: u8,
-    },
-    SkipEqReg {
-        x_register: usize,
-        y_register: usize,
-    },
-    SkipNeReg {
-        x_register: usize,
-        y_register: usize,
-    },
-    SetRegister {
-        register: usize,
-        value: u8,
-    },
-    AddRegister {
-        register: usize,
-        value: u8,
-    },
-    SetIndex(u16),
-    Draw {
-        x_register: usize,
-        y_register: usize,
-        count: u8,
-    },
-}
+    /// Decodes the loaded program without executing it, yielding the address of every instruction.
+    /// The operand word of F000 NNNN is skipped.
+    pub fn instructions(&self) -> impl Iterator<Item = (u16, Result<Instruction, Chip8Error>)> + '_ {
+        let end = self.program_start as usize + self.program_len;
+        let mut address = self.program_start as usize;
+        core::iter::from_fn(move || {
+            if address >= end {
+                return None;
+            }
+            let instruction = self.memory.get_instruction(address).and_then(Instruction::try_from);
+            let current = address as u16;
+            address += if instruction == Ok(Instruction::SetIndexLong) { 4 } else { 2 };
+            Some((current, instruction))
+        })
+    }
 
+    /// Removes the loaded program by zeroing memory from 0x200 onward and resets the CPU,
+    /// the fonts stay in memory so another program can be loaded.
+    pub fn unload(&mut self) {
+        self.memory.clear_from(PROGRAM_START as usize);
+        self.program_start = PROGRAM_START;
+        self.program_len = 0;
+        self.reset();
+    }
 
-impl TryFrom<u16> for Instruction {
-    type Error = String;
+    /// Restarts the loaded program, the font and program bytes stay in memory.
+    pub fn reset(&mut self) {
+        // this also clears the screen and leaves the high-res mode of a SUPER-CHIP program
+        self.display.set_resolution(LOWRES_WIDTH, LOWRES_HEIGHT);
+        self.plane = 1;
+        self.program_counter = self.program_start;
+        self.resume_breakpoint = None;
+        self.halted = false;
+        self.index_register = 0;
+        self.stack.clear();
+        self.delay_timer.inner = 0;
+        self.sound_timer.inner = 0;
+        self.variable_registers = [0u8; VARIABLE_REGISTER_SIZE];
+    }
 
-    fn try_from(instruction: u16) -> Result<Self, Self::Error> {
-        let first = 0b1111 & (instruction >> 12) as u8;
-        let second = 0b1111 & (instruction >> 8) as u8;
-        let third = 0b1111 & (instruction >> 4) as u8;
-        let fourth = 0b1111 & instruction as u8;
-        let number = 0b1111_1111 & instruction as u8;
-        let address = 0b1111_1111_1111 & instruction;
-        match first {
-            0x0 => {
-                if second == 0x0 {
-                    if third == 0xE {
-                        if fourth == 0x0 {
-                            return Ok(Instruction::ClearScreen);
-                        }
-                        if fourth == 0xE {
-                            return Ok(Instruction::Return);
-                        }
-                    }
+    /// Executes up to `ticks` instructions, stopping early when the program halts,
+    /// waits for a key or the display, or reaches a breakpoint.
+    pub fn update(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.paused {
+            return Ok(StepOutcome::Continued);
+        }
+        self.begin_frame();
+        let outcome = self.run_frame_cycles(self.ticks as u128);
+        self.end_frame();
+        outcome
+    }
+
+    fn begin_frame(&mut self) {
+        #[cfg(feature = "alloc")]
+        self.record_frame_start();
+        self.waiting_for_vblank = false;
+    }
+
+    /// Records the rewind snapshot and applies the queued input of the frame that is about to run.
+    #[cfg(feature = "alloc")]
+    fn record_frame_start(&mut self) {
+        let mut snapshot = self.rewind_buffer.is_some().then(|| self.snapshot());
+        while let Some(entry) = self.input_queue.first_entry() {
+            if *entry.key() > self.frame_counter {
+                break;
+            }
+            for (key, down) in entry.remove() {
+                self.on_input(key, down);
+                if let Some(snapshot) = snapshot.as_mut() {
+                    snapshot.input.push((key, down));
                 }
             }
-            0x1 => {
-                return Ok(Instruction::Jump(address));
+        }
+        if let (Some(snapshot), Some(buffer)) = (snapshot, self.rewind_buffer.as_mut()) {
+            if buffer.len() == self.rewind_capacity {
+                buffer.pop_front();
             }
-            0x2 => {
-                return Ok(Instruction::Call(address));
+            buffer.push_back(snapshot);
+        }
+    }
+
+    /// Executes up to `cycles` instructions of a frame, stopping like `update` describes.
+    fn run_frame_cycles(&mut self, cycles: u128) -> Result<StepOutcome, Chip8Error> {
+        let mut outcome = StepOutcome::Continued;
+        for _ in 0..cycles {
+            if self.halted {
+                outcome = StepOutcome::Halted;
+                break;
             }
-            0x6 => {
-                if second > 0xF {
-                    return Err(format!("instruction contains invalid register {second}"));
-                }
-                return Ok(Instruction::SetRegister { register: second as usize, value: number });
+            if self.waiting_for_vblank {
+                break;
             }
-            0x7 => {
-                return Ok(Instruction::AddRegister { register: second as usize, value: number });
+            outcome = self.execute_next()?;
+            if outcome != StepOutcome::Continued {
+                break;
             }
-            0xA => {
-                return Ok(Instruction::SetIndex(address));
+        }
+        Ok(outcome)
+    }
+
+    fn end_frame(&mut self) {
+        self.frame_counter += 1;
+        if !self.fast_forwarding {
+            self.notify_display_changed();
+        }
+    }
+
+    fn notify_display_changed(&mut self) {
+        if self.display.changed {
+            self.display.changed = false;
+            #[cfg(feature = "alloc")]
+            if let Some(callback) = self.display_callback.as_mut() {
+                callback(self.display.pixels());
             }
-            0xD => {
-                return Ok(Instruction::Draw { x_register: second as usize, y_register: third as usize, count: fourth });
+        }
+    }
+
+    /// Runs `frames` frames like `run_frames` but without tracking dirty pixels or calling the
+    /// display callback in between, afterwards the whole screen is dirty and the callback fires once.
+    pub fn fast_forward(&mut self, frames: usize) -> Result<(), Chip8Error> {
+        self.fast_forwarding = true;
+        self.display.track_dirty = false;
+        let result = self.run_frames(frames);
+        self.fast_forwarding = false;
+        self.display.track_dirty = true;
+
+        self.display.mark_all_dirty();
+        self.notify_display_changed();
+        result.map(|_| ())
+    }
+
+    /// Returns whether the program ended by jumping to its own address.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Keeps the state before the last `frames` calls of `update` for `rewind`, 0 disables it.
+    #[cfg(feature = "alloc")]
+    pub fn set_rewind_capacity(&mut self, frames: usize) {
+        self.rewind_capacity = frames;
+        self.rewind_buffer = (frames > 0).then(|| VecDeque::with_capacity(frames));
+    }
+
+    /// Restores the state from before the most recent recorded `update`,
+    /// returns false if there is nothing left to rewind.
+    #[cfg(feature = "alloc")]
+    pub fn rewind(&mut self) -> bool {
+        let Some(snapshot) = self.rewind_buffer.as_mut().and_then(|buffer| buffer.pop_back()) else {
+            return false;
+        };
+        self.memory.bytes_mut().copy_from_slice(&snapshot.memory);
+        let (width, height) = snapshot.resolution;
+        self.display.restore_planes(width, height, &snapshot.planes);
+        self.keypad = snapshot.keypad;
+        if !snapshot.input.is_empty() {
+            self.input_queue.entry(snapshot.frame_counter).or_default().splice(0..0, snapshot.input);
+        }
+        self.program_counter = snapshot.program_counter;
+        self.resume_breakpoint = None;
+        self.index_register = snapshot.index_register;
+        self.stack = snapshot.stack;
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.variable_registers = snapshot.variable_registers;
+        self.rpl_flags = snapshot.rpl_flags;
+        self.plane = snapshot.plane;
+        self.rng = snapshot.rng;
+        self.halted = snapshot.halted;
+        self.frame_counter = snapshot.frame_counter;
+        true
+    }
+
+    #[cfg(feature = "alloc")]
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.bytes().to_vec(),
+            planes: self.display.packed_planes(),
+            resolution: self.resolution(),
+            keypad: self.keypad.clone(),
+            input: Vec::new(),
+            program_counter: self.program_counter,
+            index_register: self.index_register,
+            stack: self.stack.clone(),
+            delay_timer: self.delay_timer.clone(),
+            sound_timer: self.sound_timer.clone(),
+            variable_registers: self.variable_registers,
+            rpl_flags: self.rpl_flags,
+            plane: self.plane,
+            rng: self.rng.clone(),
+            halted: self.halted,
+            frame_counter: self.frame_counter,
+        }
+    }
+
+    /// Skips opcodes that do not decode instead of failing, each one is reported as a
+    /// `TraceEvent::UnknownInstruction`. This shows how far a ROM gets and what is missing.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Freezes the CPU and timers, the screen can still be rendered while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Runs `frames` calls of `update` followed by `tick_timers` without a host loop,
+    /// a breakpoint ends the run early.
+    pub fn run_frames(&mut self, frames: usize) -> Result<StepOutcome, Chip8Error> {
+        let mut outcome = StepOutcome::Continued;
+        for _ in 0..frames {
+            outcome = self.update()?;
+            self.tick_timers();
+            if let StepOutcome::Breakpoint(_) = outcome {
+                break;
             }
-            _ => {}
         }
-        Err(format!("unknown instruction:{:#06x}", instruction))
+        Ok(outcome)
     }
-}
 
-impl std::fmt::Display for Instruction {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Instruction::ClearScreen => write!(f, "clear screen"),
-            Instruction::Jump(address) => write!(f, "jump {address}"),
-            Instruction::Call(address) => write!(f, "call {address}"),
-            Instruction::Return => write!(f, "return"),
-            Instruction::SkipEqVal { register, value } => write!(f, "skip if value equals register {register} {value}"),
-            Instruction::SkipNeVal { register, value } => write!(f, "skip if value does not equals register {register} {value}"),
-            Instruction::SkipEqReg { x_register, y_register } => write!(f, "skip if registers are equal {x_register} {y_register}"),
-            Instruction::SkipNeReg { x_register, y_register } => write!(f, "skip if registers are not equal {x_register} {y_register}"),
-            Instruction::SetRegister { register, value } => write!(f, "set register {register} {value}"),
-            Instruction::AddRegister { register, value } => write!(f, "add register {register} {value}"),
-            Instruction::SetIndex(address) => write!(f, "set index {address}"),
-            Instruction::Draw { x_register, y_register, count } => write!(f, "draw x: {x_register} y: {y_register} height: {count}"),
-        }
-    }
-}
\ No newline at end of file
+    /// Runs frames until `predicate` holds or `max_frames` have run, returns whether `predicate` held.
+    /// A breakpoint ends the run early.
+    pub fn run_until<F: Fn(&Chip8) -> bool>(&mut self, predicate: F, max_frames: usize) -> Result<bool, Chip8Error> {
+        for _ in 0..max_frames {
+            if predicate(self) {
+                return Ok(true);
+            }
+            if let StepOutcome::Breakpoint(_) = self.run_frames(1)? {
+                break;
+            }
+        }
+        Ok(predicate(self))
+    }
+
+    /// Executes a single instruction and reports a changed display to the callback right away.
+    pub fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        let outcome = self.execute_next();
+        self.notify_display_changed();
+        outcome
+    }
+
+    /// Fetches and executes the instruction at the program counter without notifying the display callback.
+    fn execute_next(&mut self) -> Result<StepOutcome, Chip8Error> {
+        let pc = self.program_counter;
+        #[cfg(feature = "alloc")]
+        {
+            if self.resume_breakpoint != Some(pc) && self.breakpoints.contains(&pc) {
+                self.resume_breakpoint = Some(pc);
+                return Ok(StepOutcome::Breakpoint(pc));
+            }
+            self.resume_breakpoint = None;
+        }
+        let opcode = self.fetch()?;
+        #[cfg(feature = "alloc")]
+        if let Some(history) = self.trace_history.as_mut() {
+            if history.len() == TRACE_HISTORY_SIZE {
+                history.pop_front();
+            }
+            history.push_back((pc, opcode));
+        }
+        let instruction = match Instruction::try_from(opcode) {
+            Ok(instruction) => instruction,
+            Err(Chip8Error::UnknownInstruction(_)) if self.lenient => {
+                // the skip takes a cycle like any instruction, so a frame still runs `ticks` of them
+                self.cycles += 1;
+                self.trace(TraceEvent::UnknownInstruction { pc, opcode });
+                return Ok(StepOutcome::Continued);
+            }
+            Err(err) => return Err(err),
+        };
+        self.trace(TraceEvent::Execute { pc, opcode, instruction });
+        self.execute(instruction)?;
+        self.cycles += 1;
+
+        let outcome = if self.halted {
+            StepOutcome::Halted
+        } else if matches!(instruction, Instruction::WaitForKey { .. }) && self.program_counter == pc {
+            StepOutcome::AwaitingKey
+        } else {
+            StepOutcome::Continued
+        };
+        Ok(outcome)
+    }
+
+    /// Makes `step` and `update` stop with `StepOutcome::Breakpoint` before executing the instruction
+    /// at `addr`, calling them again continues from there.
+    #[cfg(feature = "alloc")]
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Sets the instruction frequency `update_for` runs at, 0 is treated as 1.
+    pub fn set_clock_hz(&mut self, hz: u32) {
+        self.clock_hz = hz.max(1);
+    }
+
+    pub fn clock_hz(&self) -> u32 {
+        self.clock_hz
+    }
+
+    /// Executes as many instructions as `clock_hz` allows in `elapsed`, carrying fractions of a
+    /// cycle over to the next call. Every call is one frame like `update`, with the same early
+    /// stops, queued input, rewind snapshot and display callback.
+    pub fn update_for(&mut self, elapsed: Duration) -> Result<StepOutcome, Chip8Error> {
+        if self.paused {
+            return Ok(StepOutcome::Continued);
+        }
+        let budget = elapsed.as_nanos() * self.clock_hz as u128 + self.cycle_carry;
+        self.cycle_carry = budget % 1_000_000_000;
+
+        self.begin_frame();
+        let outcome = self.run_frame_cycles(budget / 1_000_000_000);
+        self.end_frame();
+        outcome
+    }
+
+    /// Executes `instruction` as if it had been fetched from the program counter, without tracing
+    /// it or counting a cycle. The program counter moves past it first like in `step`, so jumps,
+    /// skips and FX0A behave the same, and is put back if the instruction fails.
+    pub fn execute_instruction(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        // a hand-built instruction can hold operands no opcode encodes, e.g. register 16,
+        // which would index past the registers
+        let opcode = u16::from(instruction);
+        if Instruction::try_from(opcode) != Ok(instruction) {
+            return Err(Chip8Error::UnknownInstruction(opcode));
+        }
+        let pc = self.program_counter;
+        self.skip_word()?;
+        self.execute(instruction).inspect_err(|_| self.program_counter = pc)
+    }
+
+    /// Returns how many pixels the most recent draw turned off, VF only tells whether any did.
+    pub fn last_draw_collisions(&self) -> u32 {
+        self.last_draw_collisions
+    }
+
+    /// Returns how many instructions have been executed so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Executes exactly `n` instructions independent of `ticks`, timers are not touched.
+    /// A breakpoint ends the run early, a changed display is reported once at the end.
+    pub fn run_cycles(&mut self, n: u64) -> Result<StepOutcome, Chip8Error> {
+        let mut outcome = Ok(StepOutcome::Continued);
+        for _ in 0..n {
+            outcome = self.execute_next();
+            if matches!(outcome, Err(_) | Ok(StepOutcome::Breakpoint(_))) {
+                break;
+            }
+        }
+        self.notify_display_changed();
+        outcome
+    }
+
+    /// Registers a callback that receives a `TraceEvent` before every executed instruction,
+    /// this replaces the one installed by the `debug` flag.
+    #[cfg(feature = "alloc")]
+    pub fn set_trace_callback(&mut self, f: Box<dyn FnMut(TraceEvent)>) {
+        self.trace = Some(f);
+    }
+
+    /// Registers a callback that receives `screen()` at the end of every `update` that changed a pixel.
+    #[cfg(feature = "alloc")]
+    pub fn on_display_changed(&mut self, f: DisplayCallback) {
+        self.display_callback = Some(f);
+    }
+
+    /// Keeps the last 256 fetched `(pc, opcode)` pairs for post-mortem debugging.
+    #[cfg(feature = "alloc")]
+    pub fn set_trace_history(&mut self, enabled: bool) {
+        self.trace_history = enabled.then(|| VecDeque::with_capacity(TRACE_HISTORY_SIZE));
+    }
+
+    /// Counts how often each kind of instruction executes, keyed by `Instruction::name`.
+    #[cfg(feature = "alloc")]
+    pub fn set_opcode_stats(&mut self, enabled: bool) {
+        self.opcode_stats = enabled.then(BTreeMap::new);
+    }
+
+    /// Returns the counts collected since `set_opcode_stats` enabled them.
+    #[cfg(feature = "alloc")]
+    pub fn opcode_stats(&self) -> Option<&BTreeMap<&'static str, u64>> {
+        self.opcode_stats.as_ref()
+    }
+
+    /// Returns the recorded `(pc, opcode)` pairs from oldest to newest.
+    #[cfg(feature = "alloc")]
+    pub fn recent_trace(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.trace_history.iter().flatten().copied()
+    }
+
+    fn trace(&mut self, event: TraceEvent) {
+        #[cfg(feature = "alloc")]
+        if let Some(callback) = self.trace.as_mut() {
+            callback(event);
+        }
+        #[cfg(not(feature = "alloc"))]
+        let _ = event;
+    }
+
+    /// Without an allocator there is no trace callback to report writes to.
+    #[cfg(not(feature = "alloc"))]
+    fn trace_writes(&mut self, _range: core::ops::Range<usize>) {}
+
+    #[cfg(feature = "alloc")]
+    fn trace_writes(&mut self, range: core::ops::Range<usize>) {
+        if self.trace.is_none() {
+            return;
+        }
+        let program_start = self.program_start as usize;
+        let program = program_start..program_start + self.program_len;
+        for addr in range.filter(|addr| program.contains(addr)) {
+            self.trace(TraceEvent::SelfModify { addr: addr as u16 });
+        }
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        if self.display.high_res() || self.quirks.scroll_low_res {
+            self.display.scroll(self.plane, dx, dy);
+        }
+    }
+
+    fn key_down(&self, key: u8) -> bool {
+        self.keypad.is_down(key)
+    }
+
+    fn register_memory_range(&self, register: usize) -> Result<core::ops::Range<usize>, Chip8Error> {
+        let begin = self.index_register as usize;
+        let end = begin + register + 1;
+        let size = self.memory.size();
+        if end > size {
+            return Err(Chip8Error::DoesNotFit { len: register + 1, addr: begin, size });
+        }
+        Ok(begin..end)
+    }
+
+    /// Moves the program counter to the next word, a 64KB memory would otherwise overflow it.
+    fn skip_word(&mut self) -> Result<(), Chip8Error> {
+        self.program_counter = self.program_counter.checked_add(2).ok_or(Chip8Error::PcOutOfBounds(self.program_counter))?;
+        Ok(())
+    }
+
+    /// Moves the program counter past the next instruction, which is two words for F000 NNNN.
+    fn skip_instruction(&mut self) -> Result<(), Chip8Error> {
+        if self.memory.get_instruction(self.program_counter as usize) == Ok(u16::from(Instruction::SetIndexLong)) {
+            self.skip_word()?;
+        }
+        self.skip_word()
+    }
+
+    fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        let pc = self.program_counter;
+        if pc & 1 != 0 || pc as usize >= self.memory.size() - 1 {
+            return Err(Chip8Error::PcOutOfBounds(pc));
+        }
+        let instruction = self.memory.get_instruction(self.program_counter as usize)?;
+        self.skip_word()?;
+        Ok(instruction)
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        #[cfg(feature = "alloc")]
+        if let Some(stats) = self.opcode_stats.as_mut() {
+            *stats.entry(instruction.name()).or_default() += 1;
+        }
+        match instruction {
+            Instruction::SysCall(_) => {}
+            Instruction::ClearScreen => { self.display.clear(self.plane) }
+            Instruction::ScrollDown(rows) => { self.scroll(0, rows as isize) }
+            Instruction::ScrollRight => { self.scroll(4, 0) }
+            Instruction::ScrollLeft => { self.scroll(-4, 0) }
+            Instruction::SelectPlane(mask) => { self.plane = mask & ALL_PLANES }
+            Instruction::DisableHighRes => { self.display.set_resolution(LOWRES_WIDTH, LOWRES_HEIGHT) }
+            Instruction::EnableHighRes => { self.display.set_resolution(DISPLAY_WIDTH, DISPLAY_HEIGHT) }
+            Instruction::Jump(address) => {
+                if address == self.program_counter.wrapping_sub(2) {
+                    self.halted = true;
+                }
+                self.program_counter = address;
+            }
+            Instruction::Call(address) => {
+                self.stack.push(self.program_counter)?;
+                self.program_counter = address;
+            }
+            Instruction::Return => {
+                let address = self.stack.pop()?;
+                self.program_counter = address;
+            }
+            Instruction::SkipEqVal { register, value } => {
+                if self.variable_registers[register] == value {
+                    self.skip_instruction()?;
+                }
+            }
+            Instruction::SkipNeVal { register, value } => {
+                if self.variable_registers[register] != value {
+                    self.skip_instruction()?;
+                }
+            }
+            Instruction::SkipEqReg { x_register, y_register } => {
+                if self.variable_registers[x_register] == self.variable_registers[y_register] {
+                    self.skip_instruction()?;
+                }
+            }
+            Instruction::SkipNeReg { x_register, y_register } => {
+                if self.variable_registers[x_register] != self.variable_registers[y_register] {
+                    self.skip_instruction()?;
+                }
+            }
+            Instruction::SetRegister { register, value } => { self.variable_registers[register] = value }
+            Instruction::AddRegister { register, value } => { self.variable_registers[register] = self.variable_registers[register].wrapping_add(value) }
+            Instruction::SetRegReg { x_register, y_register } => {
+                self.variable_registers[x_register] = self.variable_registers[y_register];
+            }
+            Instruction::Or { x_register, y_register } => {
+                self.variable_registers[x_register] |= self.variable_registers[y_register];
+            }
+            Instruction::And { x_register, y_register } => {
+                self.variable_registers[x_register] &= self.variable_registers[y_register];
+            }
+            Instruction::Xor { x_register, y_register } => {
+                self.variable_registers[x_register] ^= self.variable_registers[y_register];
+            }
+            Instruction::AddReg { x_register, y_register } => {
+                // both operands are read before writing and the flag is written last,
+                // so for X = F the carry replaces the sum and Y = F adds the old flag
+                let (result, carry) = self.variable_registers[x_register].overflowing_add(self.variable_registers[y_register]);
+                self.variable_registers[x_register] = result;
+                self.variable_registers[FLAG_REGISTER] = carry as u8;
+            }
+            Instruction::SubXY { x_register, y_register } => {
+                let (result, borrow) = self.variable_registers[x_register].overflowing_sub(self.variable_registers[y_register]);
+                self.variable_registers[x_register] = result;
+                self.variable_registers[FLAG_REGISTER] = !borrow as u8;
+            }
+            Instruction::ShiftRight { x_register, y_register } => {
+                let source = if self.quirks.shift_uses_vy { y_register } else { x_register };
+                let value = self.variable_registers[source];
+                self.variable_registers[x_register] = value >> 1;
+                self.variable_registers[FLAG_REGISTER] = value & 1;
+            }
+            Instruction::SubYX { x_register, y_register } => {
+                let (result, borrow) = self.variable_registers[y_register].overflowing_sub(self.variable_registers[x_register]);
+                self.variable_registers[x_register] = result;
+                self.variable_registers[FLAG_REGISTER] = !borrow as u8;
+            }
+            Instruction::ShiftLeft { x_register, y_register } => {
+                let source = if self.quirks.shift_uses_vy { y_register } else { x_register };
+                let value = self.variable_registers[source];
+                self.variable_registers[x_register] = value << 1;
+                self.variable_registers[FLAG_REGISTER] = value >> 7;
+            }
+            Instruction::SkipIfKey { register } => {
+                if self.key_down(self.variable_registers[register]) {
+                    self.skip_instruction()?;
+                }
+            }
+            Instruction::SkipIfNotKey { register } => {
+                if !self.key_down(self.variable_registers[register]) {
+                    self.skip_instruction()?;
+                }
+            }
+            Instruction::WaitForKey { register } => {
+                // like on the COSMAC VIP a key is only reported once it is released again,
+                // which takes the release so a single press completes a single FX0A
+                let key = if self.quirks.wait_key_repeats {
+                    self.keypad.first_down()
+                } else {
+                    self.keypad.take_released()
+                };
+                match key {
+                    Some(key) => self.variable_registers[register] = key,
+                    None => self.program_counter = self.program_counter.wrapping_sub(2),
+                }
+            }
+            Instruction::GetDelayTimer { register } => { self.variable_registers[register] = self.delay_timer.inner }
+            Instruction::SetDelayTimer { register } => { self.delay_timer.inner = self.variable_registers[register] }
+            Instruction::SetSoundTimer { register } => { self.sound_timer.inner = self.variable_registers[register] }
+            Instruction::SetIndex(address) => { self.index_register = address }
+            Instruction::SetIndexLong => {
+                self.index_register = self.memory.get_instruction(self.program_counter as usize)?;
+                self.skip_word()?;
+            }
+            Instruction::AddToIndex { register } => {
+                let size = self.memory.size();
+                let sum = self.index_register as usize + self.variable_registers[register] as usize;
+                self.index_register = (sum % size) as u16;
+                // the flag reports leaving the 12-bit address range even if a larger memory follows
+                if self.quirks.index_overflow_sets_flag {
+                    self.variable_registers[FLAG_REGISTER] = (sum > 0x0FFF) as u8;
+                }
+            }
+            Instruction::JumpOffset(address) => {
+                let register = if self.quirks.jump_offset_uses_vx { (address >> 8) as usize } else { 0 };
+                self.program_counter = address + self.variable_registers[register] as u16;
+            }
+            Instruction::Random { register, mask } => {
+                self.variable_registers[register] = self.rng.next_byte() & mask;
+            }
+            Instruction::SetIndexToFont { register } => {
+                let character = (self.variable_registers[register] & 0x0F) as u16;
+                self.index_register = FONT_BASE + character * FONT_CHAR_SIZE;
+            }
+            Instruction::SetIndexToBigFont { register } => {
+                let character = (self.variable_registers[register] & 0x0F) as u16;
+                self.index_register = BIG_FONT_ADDRESS + character * BIG_FONT_CHAR_SIZE;
+            }
+            Instruction::StoreBcd { register } => {
+                let index = self.index_register as usize;
+                let size = self.memory.size();
+                if index + 2 >= size {
+                    return Err(Chip8Error::OutOfBounds { addr: index + 2, size });
+                }
+                let value = self.variable_registers[register];
+                self.memory.bytes_mut()[index] = value / 100;
+                self.memory.bytes_mut()[index + 1] = value / 10 % 10;
+                self.memory.bytes_mut()[index + 2] = value % 10;
+                self.trace_writes(index..index + 3);
+            }
+            Instruction::StoreRegisters { register } => {
+                let range = self.register_memory_range(register)?;
+                self.memory.bytes_mut()[range.clone()].copy_from_slice(&self.variable_registers[..=register]);
+                self.trace_writes(range);
+                if self.quirks.memory_increments_index {
+                    self.index_register = self.index_register.wrapping_add(register as u16 + 1);
+                }
+            }
+            Instruction::LoadRegisters { register } => {
+                let range = self.register_memory_range(register)?;
+                self.variable_registers[..=register].copy_from_slice(&self.memory.bytes()[range]);
+                if self.quirks.memory_increments_index {
+                    self.index_register = self.index_register.wrapping_add(register as u16 + 1);
+                }
+            }
+            Instruction::StoreFlags { register } => {
+                let count = register.min(RPL_FLAG_COUNT - 1) + 1;
+                self.rpl_flags[..count].copy_from_slice(&self.variable_registers[..count]);
+            }
+            Instruction::LoadFlags { register } => {
+                let count = register.min(RPL_FLAG_COUNT - 1) + 1;
+                self.variable_registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+            }
+            Instruction::Draw { x_register, y_register, count } => {
+                let (width, height) = (self.display.width, self.display.height);
+                // the start position wraps around in either resolution, only the sprite itself is clipped
+                let start_x = self.variable_registers[x_register] as usize % width;
+                let start_y = self.variable_registers[y_register] as usize % height;
+                self.waiting_for_vblank = self.quirks.display_wait;
+
+                // in high-res mode a height of 0 draws a 16x16 sprite made of two bytes per row
+                let (rows, bytes_per_row) = if count == 0 && self.display.high_res() { (16, 2) } else { (count as usize, 1) };
+                // every selected plane reads its own copy of the sprite data, one after another
+                let sprite_size = rows * bytes_per_row;
+                let sprite_end = self.index_register as usize + selected_planes(self.plane).count() * sprite_size;
+                let size = self.memory.size();
+                if sprite_end > size {
+                    return Err(Chip8Error::OutOfBounds { addr: sprite_end - 1, size });
+                }
+                let mut collisions = 0;
+                for (i, plane) in selected_planes(self.plane).enumerate() {
+                    let begin = self.index_register as usize + i * sprite_size;
+                    let end = begin + sprite_size;
+                    for (row, sprite_row) in self.memory.bytes()[begin..end].chunks(bytes_per_row).enumerate() {
+                        let mut y = start_y + row;
+                        if self.quirks.wrap_y {
+                            y %= height;
+                        } else if y >= height {
+                            break;
+                        }
+
+                        let bits = sprite_row.iter().flat_map(|byte| get_bits(*byte));
+                        for (column, bit) in bits.enumerate() {
+                            let mut x = start_x + column;
+                            if self.quirks.wrap_x {
+                                x %= width;
+                            } else if x >= width {
+                                break;
+                            }
+
+                            // only pixels inside the visible area can collide, clipped ones are never drawn
+                            collisions += self.display.draw(plane, x, y, bit)? as u32;
+                        }
+                    }
+                }
+                self.last_draw_collisions = collisions;
+                self.variable_registers[FLAG_REGISTER] = (collisions > 0) as u8;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a ROM into `(address, mnemonic)` pairs as if it was loaded at 0x200, in the syntax `assemble` reads.
+/// Words that are not valid instructions, e.g. sprite data, are emitted as `DB 0xNNNN`.
+/// Bytes that would lie past the 16-bit address space are ignored.
+#[cfg(feature = "alloc")]
+pub fn disassemble(rom: &[u8]) -> Vec<(u16, String)> {
+    let addressable = XO_CHIP_RAM_SIZE - PROGRAM_START as usize;
+    let rom = &rom[..rom.len().min(addressable)];
+    let mut listing = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = PROGRAM_START + offset as u16;
+        let (mnemonic, len) = match rom[offset..] {
+            [high, low, ..] => {
+                let encoded_instruction = (high as u16) << 8 | low as u16;
+                match Instruction::try_from(encoded_instruction) {
+                    // the address of F000 NNNN is part of the instruction and not decoded on its own
+                    Ok(Instruction::SetIndexLong) if offset + 4 <= rom.len() => {
+                        let operand = (rom[offset + 2] as u16) << 8 | rom[offset + 3] as u16;
+                        (format!("{} {:#06x}", Instruction::SetIndexLong, operand), 4)
+                    }
+                    // without its address word at the end of the ROM it is only data
+                    Ok(Instruction::SetIndexLong) | Err(_) => (format!("DB {:#06x}", encoded_instruction), 2),
+                    Ok(instruction) => (instruction.to_string(), 2),
+                }
+            }
+            [byte] => (format!("DB {:#04x}", byte), 1),
+            [] => break,
+        };
+        listing.push((address, mnemonic));
+        offset += len;
+    }
+    listing
+}
+
+/// Reads the title some ROMs embed by starting with a jump over a NUL-terminated ASCII string,
+/// e.g. `12 0C "PONG 1.0" 00`. Returns `None` if there is no such string of at most 64 characters.
+#[cfg(feature = "alloc")]
+pub fn rom_title(rom: &[u8]) -> Option<String> {
+    let target = match rom {
+        [high, low, ..] if high >> 4 == 0x1 => ((*high as usize & 0x0F) << 8 | *low as usize).checked_sub(PROGRAM_START as usize)?,
+        _ => return None,
+    };
+    let text = rom.get(2..target.min(rom.len()))?;
+    let end = text.iter().position(|byte| *byte == 0).unwrap_or(text.len());
+    let title = &text[..end];
+    if title.len() < 2 || title.len() > TITLE_LIMIT || !title.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ') {
+        return None;
+    }
+    let title = String::from_utf8_lossy(title).trim().to_string();
+    (!title.is_empty()).then_some(title)
+}
+
+/// Assembles a ROM from one instruction per line, `;` starts a comment. Operands are registers
+/// `V0`-`VF` or numbers in decimal or with a `0x` prefix in hex:
+///
+/// ```text
+/// CLS  RET  SYS nnn  SCD n  SCR  SCL  LOW  HIGH  JUMP nnn  JUMP0 nnn  CALL nnn
+/// SE Vx nn|Vy  SNE Vx nn|Vy  SET Vx nn|Vy  ADD Vx nn|Vy  OR/AND/XOR/SUB/SUBN/SHR/SHL Vx Vy
+/// INDEX nnn  INDEXL nnnn  PLANE n  RAND Vx nn  DRAW Vx Vy n  SKP Vx  SKNP Vx  KEY Vx
+/// GETDELAY Vx  DELAY Vx  SOUND Vx  ADDI Vx  FONT Vx  BIGFONT Vx  BCD Vx  STORE Vx  LOAD Vx
+/// SAVEFLAGS Vx  LOADFLAGS Vx  DB 0xNN|0xNNNN
+/// ```
+#[cfg(feature = "alloc")]
+pub fn assemble(source: &str) -> Result<Vec<u8>, Chip8Error> {
+    let mut rom = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = || Chip8Error::InvalidAssembly { line: number + 1, text: line.to_string() };
+        let mut tokens = line.split_whitespace();
+        let mnemonic = tokens.next().unwrap_or_default().to_ascii_uppercase();
+        let operands: Vec<&str> = tokens.collect();
+        match (mnemonic.as_str(), operands.as_slice()) {
+            // like the output of `disassemble`, exactly four hex digits emit a word and anything else a byte
+            ("DB", [value]) => {
+                let word = parse_number(value).ok_or_else(error)?;
+                if value.len() == 6 && value.starts_with("0x") {
+                    rom.extend_from_slice(&word.to_be_bytes());
+                } else {
+                    rom.push(u8::try_from(word).map_err(|_| error())?);
+                }
+            }
+            ("INDEXL", [value]) => {
+                let address = parse_number(value).ok_or_else(error)?;
+                rom.extend_from_slice(&u16::from(Instruction::SetIndexLong).to_be_bytes());
+                rom.extend_from_slice(&address.to_be_bytes());
+            }
+            _ => {
+                let instruction = parse_instruction(&mnemonic, &operands).ok_or_else(error)?;
+                rom.extend_from_slice(&u16::from(instruction).to_be_bytes());
+            }
+        }
+    }
+    Ok(rom)
+}
+
+#[cfg(feature = "alloc")]
+enum Operand {
+    Register(usize),
+    Number(u16),
+}
+
+#[cfg(feature = "alloc")]
+fn parse_number(token: &str) -> Option<u16> {
+    match token.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => token.parse().ok(),
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn parse_operand(token: &str) -> Option<Operand> {
+    match token.strip_prefix(['V', 'v']) {
+        Some(register) if register.len() == 1 => Some(Operand::Register(usize::from_str_radix(register, 16).ok()?)),
+        _ => parse_number(token).map(Operand::Number),
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn parse_instruction(mnemonic: &str, operands: &[&str]) -> Option<Instruction> {
+    let operands = operands.iter().map(|token| parse_operand(token)).collect::<Option<Vec<_>>>()?;
+    let address = |value: u16| (value <= 0x0FFF).then_some(value);
+    let byte = |value: u16| u8::try_from(value).ok();
+    let nibble = |value: u16| (value <= 0x0F).then_some(value as u8);
+
+    use Operand::{Number, Register};
+    let instruction = match (mnemonic, operands.as_slice()) {
+        ("CLS", []) => Instruction::ClearScreen,
+        ("RET", []) => Instruction::Return,
+        // 0NNN shares its encoding with CLS, RET and the scroll opcodes, which can't be a SYS
+        ("SYS", [Number(value)]) => match Instruction::try_from(*value) {
+            Ok(instruction @ Instruction::SysCall(_)) => instruction,
+            _ => return None,
+        },
+        ("SCD", [Number(value)]) => Instruction::ScrollDown(nibble(*value)?),
+        ("SCR", []) => Instruction::ScrollRight,
+        ("SCL", []) => Instruction::ScrollLeft,
+        ("LOW", []) => Instruction::DisableHighRes,
+        ("HIGH", []) => Instruction::EnableHighRes,
+        ("JUMP", [Number(value)]) => Instruction::Jump(address(*value)?),
+        ("JUMP0", [Number(value)]) => Instruction::JumpOffset(address(*value)?),
+        ("CALL", [Number(value)]) => Instruction::Call(address(*value)?),
+        ("SE", [Register(register), Number(value)]) => Instruction::SkipEqVal { register: *register, value: byte(*value)? },
+        ("SE", [Register(x_register), Register(y_register)]) => Instruction::SkipEqReg { x_register: *x_register, y_register: *y_register },
+        ("SNE", [Register(register), Number(value)]) => Instruction::SkipNeVal { register: *register, value: byte(*value)? },
+        ("SNE", [Register(x_register), Register(y_register)]) => Instruction::SkipNeReg { x_register: *x_register, y_register: *y_register },
+        ("SET", [Register(register), Number(value)]) => Instruction::SetRegister { register: *register, value: byte(*value)? },
+        ("SET", [Register(x_register), Register(y_register)]) => Instruction::SetRegReg { x_register: *x_register, y_register: *y_register },
+        ("ADD", [Register(register), Number(value)]) => Instruction::AddRegister { register: *register, value: byte(*value)? },
+        ("ADD", [Register(x_register), Register(y_register)]) => Instruction::AddReg { x_register: *x_register, y_register: *y_register },
+        ("OR", [Register(x_register), Register(y_register)]) => Instruction::Or { x_register: *x_register, y_register: *y_register },
+        ("AND", [Register(x_register), Register(y_register)]) => Instruction::And { x_register: *x_register, y_register: *y_register },
+        ("XOR", [Register(x_register), Register(y_register)]) => Instruction::Xor { x_register: *x_register, y_register: *y_register },
+        ("SUB", [Register(x_register), Register(y_register)]) => Instruction::SubXY { x_register: *x_register, y_register: *y_register },
+        ("SUBN", [Register(x_register), Register(y_register)]) => Instruction::SubYX { x_register: *x_register, y_register: *y_register },
+        ("SHR", [Register(x_register), Register(y_register)]) => Instruction::ShiftRight { x_register: *x_register, y_register: *y_register },
+        ("SHL", [Register(x_register), Register(y_register)]) => Instruction::ShiftLeft { x_register: *x_register, y_register: *y_register },
+        ("INDEX", [Number(value)]) => Instruction::SetIndex(address(*value)?),
+        ("PLANE", [Number(value)]) => Instruction::SelectPlane(nibble(*value)?),
+        ("RAND", [Register(register), Number(value)]) => Instruction::Random { register: *register, mask: byte(*value)? },
+        ("DRAW", [Register(x_register), Register(y_register), Number(value)]) => {
+            Instruction::Draw { x_register: *x_register, y_register: *y_register, count: nibble(*value)? }
+        }
+        ("SKP", [Register(register)]) => Instruction::SkipIfKey { register: *register },
+        ("SKNP", [Register(register)]) => Instruction::SkipIfNotKey { register: *register },
+        ("KEY", [Register(register)]) => Instruction::WaitForKey { register: *register },
+        ("GETDELAY", [Register(register)]) => Instruction::GetDelayTimer { register: *register },
+        ("DELAY", [Register(register)]) => Instruction::SetDelayTimer { register: *register },
+        ("SOUND", [Register(register)]) => Instruction::SetSoundTimer { register: *register },
+        ("ADDI", [Register(register)]) => Instruction::AddToIndex { register: *register },
+        ("FONT", [Register(register)]) => Instruction::SetIndexToFont { register: *register },
+        ("BIGFONT", [Register(register)]) => Instruction::SetIndexToBigFont { register: *register },
+        ("BCD", [Register(register)]) => Instruction::StoreBcd { register: *register },
+        ("STORE", [Register(register)]) => Instruction::StoreRegisters { register: *register },
+        ("LOAD", [Register(register)]) => Instruction::LoadRegisters { register: *register },
+        ("SAVEFLAGS", [Register(register)]) => Instruction::StoreFlags { register: *register },
+        ("LOADFLAGS", [Register(register)]) => Instruction::LoadFlags { register: *register },
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+fn register_index(nibble: u8) -> Result<usize, Chip8Error> {
+    if nibble as usize >= VARIABLE_REGISTER_SIZE {
+        return Err(Chip8Error::InvalidRegister(nibble));
+    }
+    Ok(nibble as usize)
+}
+
+/// Splits a sprite byte into pixels, the most significant bit is the leftmost pixel at index 0.
+fn get_bits(byte: u8) -> [bool; 8] {
+    let mut bits = [false; 8];
+    for i in 0..8 {
+        let bit = (byte >> i) & 1;
+        bits[7 - i] = bit == 1;
+    }
+
+    bits
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN, a call of a COSMAC VIP machine code routine which is ignored.
+    SysCall(u16),
+    ClearScreen,
+    ScrollDown(u8),
+    ScrollRight,
+    ScrollLeft,
+    DisableHighRes,
+    EnableHighRes,
+    Jump(u16),
+    Call(u16),
+    Return,
+    SkipEqVal {
+        register: usize,
+        value: u8,
+    },
+    SkipNeVal {
+        register: usize,
+        value: u8,
+    },
+    SkipEqReg {
+        x_register: usize,
+        y_register: usize,
+    },
+    SkipNeReg {
+        x_register: usize,
+        y_register: usize,
+    },
+    SetRegister {
+        register: usize,
+        value: u8,
+    },
+    AddRegister {
+        register: usize,
+        value: u8,
+    },
+    SetRegReg {
+        x_register: usize,
+        y_register: usize,
+    },
+    Or {
+        x_register: usize,
+        y_register: usize,
+    },
+    And {
+        x_register: usize,
+        y_register: usize,
+    },
+    Xor {
+        x_register: usize,
+        y_register: usize,
+    },
+    AddReg {
+        x_register: usize,
+        y_register: usize,
+    },
+    SubXY {
+        x_register: usize,
+        y_register: usize,
+    },
+    ShiftRight {
+        x_register: usize,
+        y_register: usize,
+    },
+    SubYX {
+        x_register: usize,
+        y_register: usize,
+    },
+    ShiftLeft {
+        x_register: usize,
+        y_register: usize,
+    },
+    SetIndex(u16),
+    /// F000 NNNN, sets the index to the 16-bit word that follows the instruction.
+    SetIndexLong,
+    SelectPlane(u8),
+    JumpOffset(u16),
+    Random {
+        register: usize,
+        mask: u8,
+    },
+    Draw {
+        x_register: usize,
+        y_register: usize,
+        count: u8,
+    },
+    SkipIfKey {
+        register: usize,
+    },
+    SkipIfNotKey {
+        register: usize,
+    },
+    WaitForKey {
+        register: usize,
+    },
+    GetDelayTimer {
+        register: usize,
+    },
+    SetDelayTimer {
+        register: usize,
+    },
+    SetSoundTimer {
+        register: usize,
+    },
+    AddToIndex {
+        register: usize,
+    },
+    SetIndexToFont {
+        register: usize,
+    },
+    SetIndexToBigFont {
+        register: usize,
+    },
+    StoreBcd {
+        register: usize,
+    },
+    StoreRegisters {
+        register: usize,
+    },
+    LoadRegisters {
+        register: usize,
+    },
+    StoreFlags {
+        register: usize,
+    },
+    LoadFlags {
+        register: usize,
+    },
+}
+
+
+impl Instruction {
+    /// Returns the name of the variant, e.g. `"Draw"`, without its operands.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::SysCall(..) => "SysCall",
+            Instruction::ClearScreen => "ClearScreen",
+            Instruction::ScrollDown(..) => "ScrollDown",
+            Instruction::ScrollRight => "ScrollRight",
+            Instruction::ScrollLeft => "ScrollLeft",
+            Instruction::DisableHighRes => "DisableHighRes",
+            Instruction::EnableHighRes => "EnableHighRes",
+            Instruction::Jump(..) => "Jump",
+            Instruction::Call(..) => "Call",
+            Instruction::Return => "Return",
+            Instruction::SkipEqVal { .. } => "SkipEqVal",
+            Instruction::SkipNeVal { .. } => "SkipNeVal",
+            Instruction::SkipEqReg { .. } => "SkipEqReg",
+            Instruction::SkipNeReg { .. } => "SkipNeReg",
+            Instruction::SetRegister { .. } => "SetRegister",
+            Instruction::AddRegister { .. } => "AddRegister",
+            Instruction::SetRegReg { .. } => "SetRegReg",
+            Instruction::Or { .. } => "Or",
+            Instruction::And { .. } => "And",
+            Instruction::Xor { .. } => "Xor",
+            Instruction::AddReg { .. } => "AddReg",
+            Instruction::SubXY { .. } => "SubXY",
+            Instruction::ShiftRight { .. } => "ShiftRight",
+            Instruction::SubYX { .. } => "SubYX",
+            Instruction::ShiftLeft { .. } => "ShiftLeft",
+            Instruction::SetIndex(..) => "SetIndex",
+            Instruction::SetIndexLong => "SetIndexLong",
+            Instruction::SelectPlane(..) => "SelectPlane",
+            Instruction::JumpOffset(..) => "JumpOffset",
+            Instruction::Random { .. } => "Random",
+            Instruction::Draw { .. } => "Draw",
+            Instruction::SkipIfKey { .. } => "SkipIfKey",
+            Instruction::SkipIfNotKey { .. } => "SkipIfNotKey",
+            Instruction::WaitForKey { .. } => "WaitForKey",
+            Instruction::GetDelayTimer { .. } => "GetDelayTimer",
+            Instruction::SetDelayTimer { .. } => "SetDelayTimer",
+            Instruction::SetSoundTimer { .. } => "SetSoundTimer",
+            Instruction::AddToIndex { .. } => "AddToIndex",
+            Instruction::SetIndexToFont { .. } => "SetIndexToFont",
+            Instruction::SetIndexToBigFont { .. } => "SetIndexToBigFont",
+            Instruction::StoreBcd { .. } => "StoreBcd",
+            Instruction::StoreRegisters { .. } => "StoreRegisters",
+            Instruction::LoadRegisters { .. } => "LoadRegisters",
+            Instruction::StoreFlags { .. } => "StoreFlags",
+            Instruction::LoadFlags { .. } => "LoadFlags",
+        }
+    }
+}
+
+impl TryFrom<u16> for Instruction {
+    type Error = Chip8Error;
+
+    fn try_from(instruction: u16) -> Result<Self, Self::Error> {
+        let first = 0b1111 & (instruction >> 12) as u8;
+        let second = 0b1111 & (instruction >> 8) as u8;
+        let third = 0b1111 & (instruction >> 4) as u8;
+        let fourth = 0b1111 & instruction as u8;
+        let number = instruction as u8;
+        let address = 0b1111_1111_1111 & instruction;
+        let x_register = register_index(second)?;
+        let y_register = register_index(third)?;
+        match first {
+            0x0 => match (second, number) {
+                (0x0, 0xE0) => return Ok(Instruction::ClearScreen),
+                (0x0, 0xEE) => return Ok(Instruction::Return),
+                (0x0, 0xFB) => return Ok(Instruction::ScrollRight),
+                (0x0, 0xFC) => return Ok(Instruction::ScrollLeft),
+                (0x0, 0xFE) => return Ok(Instruction::DisableHighRes),
+                (0x0, 0xFF) => return Ok(Instruction::EnableHighRes),
+                (0x0, _) if third == 0xC => return Ok(Instruction::ScrollDown(fourth)),
+                _ => return Ok(Instruction::SysCall(address)),
+            },
+            0x1 => {
+                return Ok(Instruction::Jump(address));
+            }
+            0x2 => {
+                return Ok(Instruction::Call(address));
+            }
+            0x3 => {
+                return Ok(Instruction::SkipEqVal { register: x_register, value: number });
+            }
+            0x4 => {
+                return Ok(Instruction::SkipNeVal { register: x_register, value: number });
+            }
+            0x5 if fourth == 0x0 => {
+                return Ok(Instruction::SkipEqReg { x_register, y_register });
+            }
+            0x6 => {
+                return Ok(Instruction::SetRegister { register: x_register, value: number });
+            }
+            0x7 => {
+                return Ok(Instruction::AddRegister { register: x_register, value: number });
+            }
+            0x8 => {
+                match fourth {
+                    0x0 => return Ok(Instruction::SetRegReg { x_register, y_register }),
+                    0x1 => return Ok(Instruction::Or { x_register, y_register }),
+                    0x2 => return Ok(Instruction::And { x_register, y_register }),
+                    0x3 => return Ok(Instruction::Xor { x_register, y_register }),
+                    0x4 => return Ok(Instruction::AddReg { x_register, y_register }),
+                    0x5 => return Ok(Instruction::SubXY { x_register, y_register }),
+                    0x6 => return Ok(Instruction::ShiftRight { x_register, y_register }),
+                    0x7 => return Ok(Instruction::SubYX { x_register, y_register }),
+                    0xE => return Ok(Instruction::ShiftLeft { x_register, y_register }),
+                    _ => {}
+                }
+            }
+            0x9 if fourth == 0x0 => {
+                return Ok(Instruction::SkipNeReg { x_register, y_register });
+            }
+            0xA => {
+                return Ok(Instruction::SetIndex(address));
+            }
+            0xB => {
+                return Ok(Instruction::JumpOffset(address));
+            }
+            0xC => {
+                return Ok(Instruction::Random { register: x_register, mask: number });
+            }
+            0xD => {
+                return Ok(Instruction::Draw { x_register, y_register, count: fourth });
+            }
+            0xE => {
+                let register = x_register;
+                match number {
+                    0x9E => return Ok(Instruction::SkipIfKey { register }),
+                    0xA1 => return Ok(Instruction::SkipIfNotKey { register }),
+                    _ => {}
+                }
+            }
+            0xF => {
+                let register = x_register;
+                match number {
+                    0x00 if second == 0x0 => return Ok(Instruction::SetIndexLong),
+                    0x01 => return Ok(Instruction::SelectPlane(second)),
+                    0x07 => return Ok(Instruction::GetDelayTimer { register }),
+                    0x0A => return Ok(Instruction::WaitForKey { register }),
+                    0x15 => return Ok(Instruction::SetDelayTimer { register }),
+                    0x18 => return Ok(Instruction::SetSoundTimer { register }),
+                    0x1E => return Ok(Instruction::AddToIndex { register }),
+                    0x29 => return Ok(Instruction::SetIndexToFont { register }),
+                    0x30 => return Ok(Instruction::SetIndexToBigFont { register }),
+                    0x33 => return Ok(Instruction::StoreBcd { register }),
+                    0x55 => return Ok(Instruction::StoreRegisters { register }),
+                    0x65 => return Ok(Instruction::LoadRegisters { register }),
+                    0x75 => return Ok(Instruction::StoreFlags { register }),
+                    0x85 => return Ok(Instruction::LoadFlags { register }),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Err(Chip8Error::UnknownInstruction(instruction))
+    }
+}
+
+impl From<Instruction> for u16 {
+    fn from(instruction: Instruction) -> Self {
+        let xy = |x: usize, y: usize, n: u16| (x as u16) << 8 | (y as u16) << 4 | n;
+        let xnn = |x: usize, nn: u8| (x as u16) << 8 | nn as u16;
+        match instruction {
+            Instruction::SysCall(address) => address & 0x0FFF,
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::ScrollDown(rows) => 0x00C0 | (rows & 0x0F) as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::DisableHighRes => 0x00FE,
+            Instruction::EnableHighRes => 0x00FF,
+            Instruction::Jump(address) => 0x1000 | address & 0x0FFF,
+            Instruction::Call(address) => 0x2000 | address & 0x0FFF,
+            Instruction::Return => 0x00EE,
+            Instruction::SkipEqVal { register, value } => 0x3000 | xnn(register, value),
+            Instruction::SkipNeVal { register, value } => 0x4000 | xnn(register, value),
+            Instruction::SkipEqReg { x_register, y_register } => 0x5000 | xy(x_register, y_register, 0x0),
+            Instruction::SkipNeReg { x_register, y_register } => 0x9000 | xy(x_register, y_register, 0x0),
+            Instruction::SetRegister { register, value } => 0x6000 | xnn(register, value),
+            Instruction::AddRegister { register, value } => 0x7000 | xnn(register, value),
+            Instruction::SetRegReg { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0x0),
+            Instruction::Or { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0x1),
+            Instruction::And { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0x2),
+            Instruction::Xor { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0x3),
+            Instruction::AddReg { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0x4),
+            Instruction::SubXY { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0x5),
+            Instruction::ShiftRight { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0x6),
+            Instruction::SubYX { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0x7),
+            Instruction::ShiftLeft { x_register, y_register } => 0x8000 | xy(x_register, y_register, 0xE),
+            Instruction::SetIndex(address) => 0xA000 | address & 0x0FFF,
+            Instruction::SetIndexLong => 0xF000,
+            Instruction::SelectPlane(planes) => 0xF001 | ((planes & 0x0F) as u16) << 8,
+            Instruction::JumpOffset(address) => 0xB000 | address & 0x0FFF,
+            Instruction::Random { register, mask } => 0xC000 | xnn(register, mask),
+            Instruction::Draw { x_register, y_register, count } => 0xD000 | xy(x_register, y_register, (count & 0x0F) as u16),
+            Instruction::SkipIfKey { register } => 0xE09E | xnn(register, 0),
+            Instruction::SkipIfNotKey { register } => 0xE0A1 | xnn(register, 0),
+            Instruction::WaitForKey { register } => 0xF00A | xnn(register, 0),
+            Instruction::GetDelayTimer { register } => 0xF007 | xnn(register, 0),
+            Instruction::SetDelayTimer { register } => 0xF015 | xnn(register, 0),
+            Instruction::SetSoundTimer { register } => 0xF018 | xnn(register, 0),
+            Instruction::AddToIndex { register } => 0xF01E | xnn(register, 0),
+            Instruction::SetIndexToFont { register } => 0xF029 | xnn(register, 0),
+            Instruction::SetIndexToBigFont { register } => 0xF030 | xnn(register, 0),
+            Instruction::StoreBcd { register } => 0xF033 | xnn(register, 0),
+            Instruction::StoreRegisters { register } => 0xF055 | xnn(register, 0),
+            Instruction::LoadRegisters { register } => 0xF065 | xnn(register, 0),
+            Instruction::StoreFlags { register } => 0xF075 | xnn(register, 0),
+            Instruction::LoadFlags { register } => 0xF085 | xnn(register, 0),
+        }
+    }
+}
+
+impl core::fmt::Display for Instruction {
+    /// Writes the instruction in the syntax `assemble` reads, e.g. `DRAW V0 V1 5`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Instruction::SysCall(address) => write!(f, "SYS {address:#05x}"),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::ScrollDown(rows) => write!(f, "SCD {rows}"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::DisableHighRes => write!(f, "LOW"),
+            Instruction::EnableHighRes => write!(f, "HIGH"),
+            Instruction::Jump(address) => write!(f, "JUMP {address:#05x}"),
+            Instruction::Call(address) => write!(f, "CALL {address:#05x}"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::SkipEqVal { register, value } => write!(f, "SE V{register:X} {value:#04x}"),
+            Instruction::SkipNeVal { register, value } => write!(f, "SNE V{register:X} {value:#04x}"),
+            Instruction::SkipEqReg { x_register, y_register } => write!(f, "SE V{x_register:X} V{y_register:X}"),
+            Instruction::SkipNeReg { x_register, y_register } => write!(f, "SNE V{x_register:X} V{y_register:X}"),
+            Instruction::SetRegister { register, value } => write!(f, "SET V{register:X} {value:#04x}"),
+            Instruction::AddRegister { register, value } => write!(f, "ADD V{register:X} {value:#04x}"),
+            Instruction::SetRegReg { x_register, y_register } => write!(f, "SET V{x_register:X} V{y_register:X}"),
+            Instruction::Or { x_register, y_register } => write!(f, "OR V{x_register:X} V{y_register:X}"),
+            Instruction::And { x_register, y_register } => write!(f, "AND V{x_register:X} V{y_register:X}"),
+            Instruction::Xor { x_register, y_register } => write!(f, "XOR V{x_register:X} V{y_register:X}"),
+            Instruction::AddReg { x_register, y_register } => write!(f, "ADD V{x_register:X} V{y_register:X}"),
+            Instruction::SubXY { x_register, y_register } => write!(f, "SUB V{x_register:X} V{y_register:X}"),
+            Instruction::ShiftRight { x_register, y_register } => write!(f, "SHR V{x_register:X} V{y_register:X}"),
+            Instruction::SubYX { x_register, y_register } => write!(f, "SUBN V{x_register:X} V{y_register:X}"),
+            Instruction::ShiftLeft { x_register, y_register } => write!(f, "SHL V{x_register:X} V{y_register:X}"),
+            Instruction::SetIndex(address) => write!(f, "INDEX {address:#05x}"),
+            // the address is the next word in memory, `disassemble` appends it
+            Instruction::SetIndexLong => write!(f, "INDEXL"),
+            Instruction::SelectPlane(mask) => write!(f, "PLANE {mask}"),
+            Instruction::JumpOffset(address) => write!(f, "JUMP0 {address:#05x}"),
+            Instruction::Random { register, mask } => write!(f, "RAND V{register:X} {mask:#04x}"),
+            Instruction::Draw { x_register, y_register, count } => write!(f, "DRAW V{x_register:X} V{y_register:X} {count}"),
+            Instruction::SkipIfKey { register } => write!(f, "SKP V{register:X}"),
+            Instruction::SkipIfNotKey { register } => write!(f, "SKNP V{register:X}"),
+            Instruction::WaitForKey { register } => write!(f, "KEY V{register:X}"),
+            Instruction::GetDelayTimer { register } => write!(f, "GETDELAY V{register:X}"),
+            Instruction::SetDelayTimer { register } => write!(f, "DELAY V{register:X}"),
+            Instruction::SetSoundTimer { register } => write!(f, "SOUND V{register:X}"),
+            Instruction::AddToIndex { register } => write!(f, "ADDI V{register:X}"),
+            Instruction::SetIndexToFont { register } => write!(f, "FONT V{register:X}"),
+            Instruction::SetIndexToBigFont { register } => write!(f, "BIGFONT V{register:X}"),
+            Instruction::StoreBcd { register } => write!(f, "BCD V{register:X}"),
+            Instruction::StoreRegisters { register } => write!(f, "STORE V{register:X}"),
+            Instruction::LoadRegisters { register } => write!(f, "LOAD V{register:X}"),
+            Instruction::StoreFlags { register } => write!(f, "SAVEFLAGS V{register:X}"),
+            Instruction::LoadFlags { register } => write!(f, "LOADFLAGS V{register:X}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(program: &[u16]) -> Vec<u8> {
+        program.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    fn chip8(program: &[u16]) -> Chip8 {
+        let mut chip = Chip8::new_with_seed(1, false, 1).unwrap();
+        chip.load_program(&words(program)).unwrap();
+        chip
+    }
+
+    fn run(chip: &mut Chip8, steps: usize) {
+        for _ in 0..steps {
+            chip.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn add_reg_sets_carry() {
+        let mut chip = chip8(&[0x60FF, 0x6101, 0x8014]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0], 0x00);
+        assert_eq!(chip.registers()[0xF], 1);
+
+        let mut chip = chip8(&[0x60FE, 0x6101, 0x8014]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0], 0xFF);
+        assert_eq!(chip.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn sub_sets_not_borrow() {
+        let mut chip = chip8(&[0x6000, 0x6101, 0x8015]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0], 0xFF);
+        assert_eq!(chip.registers()[0xF], 0);
+
+        let mut chip = chip8(&[0x6001, 0x6101, 0x8015]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0], 0x00);
+        assert_eq!(chip.registers()[0xF], 1);
+
+        let mut chip = chip8(&[0x6001, 0x6100, 0x8017]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0], 0xFF);
+        assert_eq!(chip.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn logic_and_shift() {
+        let mut chip = chip8(&[0x600C, 0x610A, 0x8010, 0x8011, 0x6203, 0x8021, 0x630A, 0x8032, 0x640F, 0x8043]);
+        run(&mut chip, 4);
+        assert_eq!(chip.registers()[0], 0x0A);
+        run(&mut chip, 2);
+        assert_eq!(chip.registers()[0], 0x0B);
+        run(&mut chip, 2);
+        assert_eq!(chip.registers()[0], 0x0A);
+        run(&mut chip, 2);
+        assert_eq!(chip.registers()[0], 0x05);
+
+        let mut chip = chip8(&[0x6081, 0x8006, 0x6181, 0x811E]);
+        run(&mut chip, 2);
+        assert_eq!((chip.registers()[0], chip.registers()[0xF]), (0x40, 1));
+        run(&mut chip, 2);
+        assert_eq!((chip.registers()[1], chip.registers()[0xF]), (0x02, 1));
+    }
+
+    #[test]
+    fn flag_is_written_last() {
+        // VF as X holds the carry afterwards, not the sum
+        let mut chip = chip8(&[0x6FFF, 0x6101, 0x8F14]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0xF], 1);
+
+        let mut chip = chip8(&[0x6F00, 0x6101, 0x8F15]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn random_is_reproducible_with_a_seed() {
+        let program = [0xC0FF, 0xC1FF, 0xC2FF, 0xC3FF];
+        let mut first = Chip8::new_with_seed(4, false, 42).unwrap();
+        let mut second = Chip8::new_with_seed(4, false, 42).unwrap();
+        let mut other = Chip8::new_with_seed(4, false, 43).unwrap();
+        for chip in [&mut first, &mut second, &mut other] {
+            chip.load_program(&words(&program)).unwrap();
+            chip.update().unwrap();
+        }
+        assert_eq!(first.registers()[..4], second.registers()[..4]);
+        assert_ne!(first.registers()[..4], other.registers()[..4]);
+    }
+
+    #[test]
+    fn random_applies_mask() {
+        let mut chip = chip8(&[0xC00F; 64]);
+        for _ in 0..64 {
+            chip.step().unwrap();
+            assert_eq!(chip.registers()[0] & 0xF0, 0);
+        }
+        let mut chip = chip8(&[0xC000]);
+        run(&mut chip, 1);
+        assert_eq!(chip.registers()[0], 0);
+    }
+
+    #[test]
+    fn bcd_splits_digits() {
+        for (value, digits) in [(0u8, [0, 0, 0]), (9, [0, 0, 9]), (255, [2, 5, 5])] {
+            let mut chip = chip8(&[0x6000 | value as u16, 0xA300, 0xF033]);
+            run(&mut chip, 3);
+            assert_eq!(chip.dump_memory(0x300..0x303).unwrap(), digits);
+        }
+    }
+
+    #[test]
+    fn bcd_rejects_index_past_memory() {
+        let mut chip = chip8(&[0xAFFE, 0xF033]);
+        run(&mut chip, 1);
+        assert_eq!(chip.step(), Err(Chip8Error::OutOfBounds { addr: 0x1000, size: RAM_SIZE }));
+    }
+
+    fn chip8_with_quirks(program: &[u16], quirks: Quirks) -> Chip8 {
+        let mut chip = Chip8::builder().seed(1).quirks(quirks).build().unwrap();
+        chip.load_program(&words(program)).unwrap();
+        chip
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        let program = [0x6011, 0x6122, 0x6233, 0xA300, 0xF255, 0x6000, 0x6100, 0x6200, 0xA300, 0xF165];
+        let mut chip = chip8(&program);
+        run(&mut chip, 5);
+        assert_eq!(chip.dump_memory(0x300..0x304).unwrap(), [0x11, 0x22, 0x33, 0x00]);
+        assert_eq!(chip.index(), 0x300);
+        run(&mut chip, 5);
+        assert_eq!(chip.registers()[..3], [0x11, 0x22, 0x00]);
+        assert_eq!(chip.index(), 0x300);
+    }
+
+    #[test]
+    fn store_and_load_increment_index_with_quirk() {
+        let quirks = Quirks { memory_increments_index: true, ..Quirks::default() };
+        let mut chip = chip8_with_quirks(&[0xA300, 0xF255, 0xF065], quirks);
+        run(&mut chip, 2);
+        assert_eq!(chip.index(), 0x303);
+        run(&mut chip, 1);
+        assert_eq!(chip.index(), 0x304);
+    }
+
+    #[test]
+    fn store_registers_rejects_index_past_memory() {
+        let mut chip = chip8(&[0xAFFE, 0xF255]);
+        run(&mut chip, 1);
+        assert_eq!(chip.step(), Err(Chip8Error::DoesNotFit { len: 3, addr: 0xFFE, size: RAM_SIZE }));
+        let mut chip = chip8(&[0xAFFF, 0xF165]);
+        run(&mut chip, 1);
+        assert_eq!(chip.step(), Err(Chip8Error::DoesNotFit { len: 2, addr: 0xFFF, size: RAM_SIZE }));
+    }
+
+    #[test]
+    fn skip_if_key() {
+        let program = [0x6005, 0xE09E, 0x6101, 0x6202];
+        let mut chip = chip8(&program);
+        run(&mut chip, 2);
+        assert_eq!(chip.program_counter(), 0x204);
+
+        let mut chip = chip8(&program);
+        chip.on_input(5, true);
+        run(&mut chip, 2);
+        assert_eq!(chip.program_counter(), 0x206);
+    }
+
+    #[test]
+    fn skip_if_not_key() {
+        let program = [0x6005, 0xE0A1, 0x6101, 0x6202];
+        let mut chip = chip8(&program);
+        run(&mut chip, 2);
+        assert_eq!(chip.program_counter(), 0x206);
+
+        let mut chip = chip8(&program);
+        chip.on_input(5, true);
+        chip.on_input(5, false);
+        chip.on_input(6, true);
+        run(&mut chip, 2);
+        assert_eq!(chip.program_counter(), 0x206);
+
+        chip.reset();
+        chip.on_input(5, true);
+        run(&mut chip, 2);
+        assert_eq!(chip.program_counter(), 0x204);
+    }
+
+    #[test]
+    fn wait_for_key_blocks_until_a_key_is_pressed() {
+        let mut chip = chip8(&[0xF30A, 0x6001]);
+        assert_eq!(chip.update(), Ok(StepOutcome::AwaitingKey));
+        assert_eq!(chip.program_counter(), 0x200);
+        chip.tick_timers();
+
+        chip.on_input(0xB, true);
+        chip.update().unwrap();
+        chip.tick_timers();
+        chip.on_input(0xB, false);
+        assert_eq!(chip.update(), Ok(StepOutcome::Continued));
+        assert_eq!(chip.registers()[3], 0xB);
+        assert_eq!(chip.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn delay_timer_round_trip() {
+        let mut chip = chip8(&[0x6042, 0xF015, 0xF107, 0x6107, 0xF118]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[1], 0x42);
+        assert_eq!(chip.delay_timer(), 0x42);
+        run(&mut chip, 2);
+        assert_eq!(chip.sound_timer(), 0x07);
+    }
+
+    #[test]
+    fn timers_saturate_at_zero() {
+        let mut chip = chip8(&[0x6002, 0xF015, 0x6001, 0xF018]);
+        run(&mut chip, 4);
+        assert!(chip.sound_active());
+        chip.tick_timers();
+        assert_eq!((chip.delay_timer(), chip.sound_timer()), (1, 0));
+        assert!(!chip.sound_active());
+        chip.tick_timers();
+        chip.tick_timers();
+        assert_eq!((chip.delay_timer(), chip.sound_timer()), (0, 0));
+    }
+
+    #[test]
+    fn timers_tick_independently_of_ticks() {
+        let mut chip = chip8(&[0x603C, 0xF015, 0x1204]);
+        chip.set_ticks(100);
+        chip.update().unwrap();
+        assert_eq!(chip.delay_timer(), 60);
+        chip.tick_timers();
+        assert_eq!(chip.delay_timer(), 59);
+    }
+
+    #[test]
+    fn jump_offset_uses_v0() {
+        let mut chip = chip8(&[0x6004, 0x6140, 0xB300]);
+        run(&mut chip, 3);
+        assert_eq!(chip.program_counter(), 0x304);
+    }
+
+    #[test]
+    fn jump_offset_uses_vx_with_quirk() {
+        let quirks = Quirks { jump_offset_uses_vx: true, ..Quirks::default() };
+        let mut chip = chip8_with_quirks(&[0x6004, 0x6340, 0xB300], quirks);
+        run(&mut chip, 3);
+        assert_eq!(chip.program_counter(), 0x340);
+    }
+
+    #[test]
+    fn add_to_index_wraps_at_12_bits() {
+        let mut chip = chip8(&[0xAFFE, 0x6001, 0xF01E, 0xF01E]);
+        run(&mut chip, 3);
+        assert_eq!(chip.index(), 0xFFF);
+        run(&mut chip, 1);
+        assert_eq!(chip.index(), 0x000);
+        assert_eq!(chip.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn add_to_index_sets_flag_with_quirk() {
+        let quirks = Quirks { index_overflow_sets_flag: true, ..Quirks::default() };
+        let mut chip = chip8_with_quirks(&[0xAFFE, 0x6001, 0xF01E, 0xF01E], quirks);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0xF], 0);
+        run(&mut chip, 1);
+        assert_eq!((chip.index(), chip.registers()[0xF]), (0x000, 1));
+    }
+
+    #[test]
+    fn font_character_addresses() {
+        for digit in 0..=0xFu16 {
+            let mut chip = chip8(&[0x6000 | digit, 0xF029]);
+            run(&mut chip, 2);
+            assert_eq!(chip.index(), FONT_BASE + digit * 5);
+        }
+        let mut chip = chip8(&[0x60FA, 0xF029]);
+        run(&mut chip, 2);
+        assert_eq!(chip.index(), FONT_BASE + 0xA * 5);
+    }
+
+    #[test]
+    fn add_register_wraps_without_flag() {
+        let mut chip = chip8(&[0x60FF, 0x6F05, 0x7001]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0], 0x00);
+        assert_eq!(chip.registers()[0xF], 0x05);
+    }
+
+    /// Draws the byte `0xFF` at `x`, `y` and returns the lit pixels as `(x, y)`.
+    fn draw_full_row(x: u8, y: u8) -> Vec<(usize, usize)> {
+        let mut chip = chip8(&[0x6000 | x as u16, 0x6100 | y as u16, 0xA208, 0xD011, 0xFF00]);
+        run(&mut chip, 4);
+        lit_pixels(&chip)
+    }
+
+    #[test]
+    fn sprites_are_clipped_at_the_edges() {
+        assert_eq!(draw_full_row(56, 0), (56..64).map(|x| (x, 0)).collect::<Vec<_>>());
+        assert_eq!(draw_full_row(60, 0), (60..64).map(|x| (x, 0)).collect::<Vec<_>>());
+        assert_eq!(draw_full_row(0, 31), (0..8).map(|x| (x, 31)).collect::<Vec<_>>());
+
+        let mut chip = chip8(&[0x6000, 0x611E, 0xA300, 0xD014]);
+        chip.poke_memory(0x300, 0x80).unwrap();
+        chip.poke_memory(0x301, 0x80).unwrap();
+        chip.poke_memory(0x302, 0x80).unwrap();
+        run(&mut chip, 4);
+        let lit: Vec<usize> = chip.screen().iter().enumerate().filter(|(_, color)| **color != 0).map(|(pos, _)| pos).collect();
+        assert_eq!(lit, [30 * 64, 31 * 64]);
+    }
+
+    #[test]
+    fn display_draw_rejects_the_first_invalid_pixel() {
+        let mut display = Display::new();
+        assert_eq!(display.draw(0, LOWRES_WIDTH - 1, LOWRES_HEIGHT - 1, true), Ok(false));
+        assert_eq!(display.pixels()[LOWRES_WIDTH * LOWRES_HEIGHT - 1], 1);
+        assert_eq!(display.draw(0, LOWRES_WIDTH, 0, true), Err(Chip8Error::PixelOutOfBounds { x: LOWRES_WIDTH, y: 0 }));
+        assert_eq!(display.draw(0, 0, LOWRES_HEIGHT, true), Err(Chip8Error::PixelOutOfBounds { x: 0, y: LOWRES_HEIGHT }));
+    }
+
+    #[test]
+    fn memory_load_rejects_data_past_the_end() {
+        let mut memory = Memory::new(RAM_SIZE).unwrap();
+        assert_eq!(memory.load(0xFFF, &[1]), Ok(()));
+        assert_eq!(memory.load(0xFFF, &[1, 2]), Err(Chip8Error::DoesNotFit { len: 2, addr: 0xFFF, size: RAM_SIZE }));
+        let large = alloc::vec![0u8; 0x10000];
+        assert_eq!(memory.load(0xF000, &large), Err(Chip8Error::DoesNotFit { len: 0x10000, addr: 0xF000, size: RAM_SIZE }));
+    }
+
+    #[derive(Default)]
+    struct RecordingScreen {
+        clears: Vec<Rgb>,
+        draws: Vec<(usize, usize, Rgb)>,
+    }
+
+    impl Screen for RecordingScreen {
+        fn draw(&mut self, x: usize, y: usize, color: Rgb) {
+            self.draws.push((x, y, color));
+        }
+
+        fn clear(&mut self, color: Rgb) {
+            self.clears.push(color);
+        }
+    }
+
+    #[test]
+    fn render_drives_the_screen() {
+        let mut chip = chip8(&[0x6002, 0x6103, 0xA208, 0xD011, 0xA000]);
+        run(&mut chip, 4);
+        let mut screen = RecordingScreen::default();
+        chip.render(&mut screen, &Palette::amber());
+        assert_eq!(screen.clears, [Palette::amber().bg]);
+        assert_eq!(screen.draws, [(2, 3, Palette::amber().fg), (4, 3, Palette::amber().fg)]);
+    }
+
+    #[test]
+    fn take_dirty_returns_changed_pixels_once() {
+        let mut chip = chip8(&[0x6002, 0x6103, 0xA20A, 0xD011, 0xD011, 0xC000]);
+        run(&mut chip, 4);
+        assert_eq!(chip.take_dirty(), [(2, 3, true), (3, 3, true)]);
+        assert_eq!(chip.take_dirty(), []);
+        run(&mut chip, 1);
+        assert_eq!(chip.take_dirty(), [(2, 3, false), (3, 3, false)]);
+    }
+
+    #[test]
+    fn reset_restores_the_initial_state_but_keeps_memory() {
+        let program = [0x6005, 0xA300, 0xF015, 0xD011, 0x2300];
+        let mut chip = chip8(&program);
+        let memory = chip.dump_memory(0..0x1000).unwrap();
+        run(&mut chip, 5);
+        chip.reset();
+        assert_eq!(chip.registers(), &[0; 16]);
+        assert_eq!((chip.index(), chip.program_counter(), chip.delay_timer()), (0, 0x200, 0));
+        assert!(chip.stack().is_empty());
+        assert!(chip.screen().iter().all(|color| *color == 0));
+        assert_eq!(chip.dump_memory(0..0x1000).unwrap(), memory);
+    }
+
+    #[test]
+    fn inspection_accessors() {
+        let mut chip = chip8(&[0x6A12, 0xA345, 0x2208, 0x0000, 0x00EE]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0xA], 0x12);
+        assert_eq!(chip.index(), 0x345);
+        assert_eq!(chip.program_counter(), 0x208);
+        assert_eq!(chip.stack(), [0x206]);
+        assert_eq!(chip.peek_memory(0x200), Some(0x6A));
+        assert_eq!(chip.peek_memory(FONT_BASE), Some(0xF0));
+        assert_eq!(chip.peek_memory(0x1000), None);
+    }
+
+    #[test]
+    fn step_executes_one_instruction() {
+        let mut chip = chip8(&[0x6001, 0x1300]);
+        assert_eq!(chip.step(), Ok(StepOutcome::Continued));
+        assert_eq!((chip.program_counter(), chip.registers()[0]), (0x202, 1));
+        chip.step().unwrap();
+        assert_eq!(chip.program_counter(), 0x300);
+    }
+
+    #[test]
+    fn errors_are_structured() {
+        let mut chip = chip8(&[0x00EE]);
+        assert_eq!(chip.step(), Err(Chip8Error::StackUnderflow));
+        let mut chip = chip8(&[0xFFFF]);
+        let err = chip.step().unwrap_err();
+        assert_eq!(err, Chip8Error::UnknownInstruction(0xFFFF));
+        assert_eq!(err.to_string(), "unknown instruction:0xffff");
+        let err: Box<dyn Error> = Box::new(Chip8Error::OutOfBounds { addr: 5000, size: RAM_SIZE });
+        assert_eq!(err.to_string(), "index 5000 is out of bounds, memory size is 4096");
+    }
+
+    #[test]
+    fn disassembles_the_ibm_logo_opening() {
+        let rom = [0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x1F, 0x70, 0x09, 0xFF];
+        let listing = disassemble(&rom);
+        let expected = [
+            (0x200, "CLS"),
+            (0x202, "INDEX 0x22a"),
+            (0x204, "SET V0 0x0c"),
+            (0x206, "SET V1 0x08"),
+            (0x208, "DRAW V0 V1 15"),
+            (0x20A, "ADD V0 0x09"),
+            (0x20C, "DB 0xff"),
+        ];
+        assert_eq!(listing.len(), expected.len());
+        for ((address, mnemonic), (expected_address, expected_mnemonic)) in listing.iter().zip(expected) {
+            assert_eq!((*address, mnemonic.as_str()), (expected_address, expected_mnemonic));
+        }
+        assert_eq!(disassemble(&[0xFF, 0xFF]), [(0x200, "DB 0xffff".to_string())]);
+    }
+
+    #[test]
+    fn disassemble_ignores_bytes_past_the_address_space() {
+        let listing = disassemble(&alloc::vec![0u8; 70_000]);
+        assert_eq!(listing.last().map(|(address, _)| *address), Some(0xFFFE));
+    }
+
+    #[test]
+    fn on_input_takes_hex_keys() {
+        let mut chip = chip8(&[0x1200]);
+        chip.on_input(0xF, true);
+        chip.on_input(0x10, true);
+        chip.on_input(b'q', true);
+        assert_eq!(chip.keypad.inner.iter().filter(|down| **down).count(), 1);
+        assert!(chip.key_down(0xF));
+    }
+
+    #[test]
+    fn keypad_tracks_state() {
+        let mut chip = chip8(&[0x1200]);
+        for _ in 0..1000 {
+            chip.on_input(0x1, true);
+            chip.on_input(0x2, true);
+            chip.on_input(0x1, false);
+            chip.on_input(0xC, true);
+        }
+        let mut expected = [false; KEY_COUNT];
+        expected[0x2] = true;
+        expected[0xC] = true;
+        assert_eq!(chip.keypad.inner, expected);
+    }
+
+    #[test]
+    fn ticks_can_change_at_runtime() {
+        let mut chip = chip8(&[0x7001; 32]);
+        chip.update().unwrap();
+        assert_eq!(chip.registers()[0], 1);
+        chip.set_ticks(5);
+        assert_eq!(chip.ticks(), 5);
+        chip.update().unwrap();
+        assert_eq!(chip.registers()[0], 6);
+        chip.set_ticks(0);
+        assert_eq!(chip.ticks(), 1);
+        chip.update().unwrap();
+        assert_eq!(chip.registers()[0], 7);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_state_round_trip() {
+        let program = [0x6005, 0xA300, 0xF033, 0xD011, 0x2210, 0x0000, 0x0000, 0x0000, 0x7001, 0xF015, 0x1214];
+        let mut chip = chip8(&program);
+        run(&mut chip, 6);
+        chip.frame_counter = 3;
+        let state = chip.save_state().unwrap();
+        let saved = (chip.registers().to_owned(), chip.index(), chip.program_counter(), chip.stack().to_vec(), chip.delay_timer(), chip.display_hash());
+        run(&mut chip, 10);
+        assert!(chip.is_halted());
+        assert_ne!((chip.program_counter(), chip.delay_timer()), (saved.2, saved.4));
+
+        let mut other = Chip8::new(1, false).unwrap();
+        other.load_program_at(0x400, &[0x12, 0x00, 0x12, 0x00]).unwrap();
+        for chip in [&mut chip, &mut other] {
+            chip.load_state(&state).unwrap();
+            let restored = (chip.registers().to_owned(), chip.index(), chip.program_counter(), chip.stack().to_vec(), chip.delay_timer(), chip.display_hash());
+            assert_eq!(restored, saved);
+            assert_eq!(chip.frame_counter(), 3);
+            assert_eq!(chip.instructions().count(), program.len());
+        }
+        assert!(!chip.is_halted());
+        assert_eq!(chip.dump_memory(0x300..0x303).unwrap(), [0, 0, 5]);
+        assert_eq!(chip.update(), Ok(StepOutcome::Continued));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn load_state_rejects_corrupt_data() {
+        let mut chip = chip8(&[0x1200]);
+        let state = chip.save_state().unwrap();
+        assert_eq!(chip.load_state(&state[..state.len() / 2]), Err(Chip8Error::InvalidState));
+        assert_eq!(chip.load_state(&[]), Err(Chip8Error::InvalidState));
+        let mut other = Chip8::builder().memory_size(XO_CHIP_RAM_SIZE).build().unwrap();
+        assert_eq!(other.load_state(&state), Err(Chip8Error::InvalidState));
+    }
+
+    /// Lights the pixels at `(x, y)` on the first plane.
+    fn light(chip: &mut Chip8, pixels: &[(usize, usize)]) {
+        let width = chip.display.width;
+        for (x, y) in pixels {
+            chip.display.set_pixel(0, x + y * width, true);
+        }
+    }
+
+    fn lit_pixels(chip: &Chip8) -> Vec<(usize, usize)> {
+        let (width, _) = chip.resolution();
+        chip.screen().iter().enumerate().filter(|(_, color)| **color != 0).map(|(pos, _)| (pos % width, pos / width)).collect()
+    }
+
+    #[test]
+    fn scroll_in_high_res() {
+        let mut chip = chip8(&[0x00FF, 0x00C3, 0x00FB, 0x00FC, 0x00FC]);
+        run(&mut chip, 1);
+        light(&mut chip, &[(0, 0), (127, 5), (10, 62)]);
+        run(&mut chip, 1);
+        assert_eq!(lit_pixels(&chip), [(0, 3), (127, 8)]);
+        run(&mut chip, 1);
+        assert_eq!(lit_pixels(&chip), [(4, 3)]);
+        run(&mut chip, 2);
+        assert_eq!(lit_pixels(&chip), []);
+    }
+
+    #[test]
+    fn scroll_in_low_res_depends_on_quirk() {
+        let mut chip = chip8(&[0x00C1, 0x00FB]);
+        light(&mut chip, &[(1, 1)]);
+        run(&mut chip, 2);
+        assert_eq!(lit_pixels(&chip), [(1, 1)]);
+
+        let mut chip = chip8_with_quirks(&[0x00C1, 0x00FB], Quirks::xo_chip());
+        light(&mut chip, &[(1, 1)]);
+        run(&mut chip, 2);
+        assert_eq!(lit_pixels(&chip), [(5, 2)]);
+    }
+
+    #[test]
+    fn high_res_mode_changes_the_resolution() {
+        let mut chip = chip8(&[0x00FF, 0x607F, 0x613F, 0xA20E, 0xD011, 0x00FE, 0x1200, 0x8000]);
+        assert_eq!((chip.resolution(), chip.screen().len()), ((64, 32), 64 * 32));
+        run(&mut chip, 5);
+        assert_eq!((chip.resolution(), chip.screen().len()), ((128, 64), 128 * 64));
+        assert_eq!(lit_pixels(&chip), [(127, 63)]);
+        run(&mut chip, 1);
+        assert_eq!((chip.resolution(), chip.screen().len()), ((64, 32), 64 * 32));
+        assert_eq!(lit_pixels(&chip), []);
+    }
+
+    #[test]
+    fn reset_leaves_high_res_mode() {
+        let mut chip = chip8(&[0x00FF]);
+        run(&mut chip, 1);
+        chip.reset();
+        assert_eq!(chip.resolution(), (LOWRES_WIDTH, LOWRES_HEIGHT));
+    }
+
+    #[test]
+    fn draws_16x16_sprites_in_high_res() {
+        let mut chip = chip8(&[0x00FF, 0x6004, 0x6102, 0xA300, 0xD010, 0xD010]);
+        // a diagonal from the top left to the bottom right corner
+        for row in 0..16u16 {
+            let bits = 0x8000u16 >> row;
+            chip.poke_memory(0x300 + row * 2, (bits >> 8) as u8).unwrap();
+            chip.poke_memory(0x301 + row * 2, bits as u8).unwrap();
+        }
+        run(&mut chip, 5);
+        assert_eq!(lit_pixels(&chip), (0..16).map(|i| (4 + i, 2 + i)).collect::<Vec<_>>());
+        assert_eq!(chip.registers()[0xF], 0);
+        run(&mut chip, 1);
+        assert_eq!(lit_pixels(&chip), []);
+        assert_eq!(chip.registers()[0xF], 1);
+    }
+
+    const IBM_LOGO: &[u8] = include_bytes!("../tests/roms/IBM Logo.ch8");
+
+    fn ibm_logo() -> Chip8 {
+        let mut chip = Chip8::new_with_seed(10, false, 1).unwrap();
+        chip.load_program(IBM_LOGO).unwrap();
+        chip
+    }
+
+    #[test]
+    fn run_frames_draws_the_ibm_logo() {
+        let mut chip = ibm_logo();
+        assert_eq!(chip.run_frames(3), Ok(StepOutcome::Halted));
+        assert!(chip.screen().iter().any(|color| *color != 0));
+    }
+
+    #[test]
+    fn run_until_stops_at_the_predicate() {
+        let mut chip = ibm_logo();
+        assert_eq!(chip.run_until(Chip8::is_halted, 100), Ok(true));
+        assert_eq!(chip.frame_counter(), 3);
+        let mut chip = chip8(&[0x1202, 0x1200]);
+        assert_eq!(chip.run_until(Chip8::is_halted, 10), Ok(false));
+        assert_eq!(chip.frame_counter(), 10);
+    }
+
+    #[test]
+    fn sprite_wrapping_quirk() {
+        let program = [0x603E, 0x6100, 0xA208, 0xD011, 0xFF00];
+        let mut clipped = chip8(&program);
+        run(&mut clipped, 4);
+        let quirks = Quirks { wrap_x: true, wrap_y: true, ..Quirks::default() };
+        let mut wrapped = chip8_with_quirks(&program, quirks);
+        run(&mut wrapped, 4);
+
+        assert_eq!(lit_pixels(&clipped), [(62, 0), (63, 0)]);
+        assert_eq!(lit_pixels(&wrapped), [(0, 0), (1, 0), (2, 0), (3, 0), (4, 0), (5, 0), (62, 0), (63, 0)]);
+        assert_ne!(clipped.screen(), wrapped.screen());
+    }
+
+    #[test]
+    fn quirk_presets() {
+        assert_eq!(Quirks::default(), Quirks {
+            shift_uses_vy: false,
+            memory_increments_index: false,
+            jump_offset_uses_vx: false,
+            index_overflow_sets_flag: false,
+            wrap_x: false,
+            wrap_y: false,
+            display_wait: false,
+            wait_key_repeats: false,
+            scroll_low_res: false,
+        });
+        assert_eq!(Quirks::cosmac_vip(), Quirks { shift_uses_vy: true, memory_increments_index: true, display_wait: true, ..Quirks::default() });
+        assert_eq!(Quirks::schip(), Quirks { jump_offset_uses_vx: true, ..Quirks::default() });
+        assert_eq!(Quirks::xo_chip(), Quirks {
+            shift_uses_vy: true,
+            memory_increments_index: true,
+            wrap_x: true,
+            wrap_y: true,
+            scroll_low_res: true,
+            ..Quirks::default()
+        });
+        let chip = Chip8::new_with_quirks(1, false, Quirks::schip()).unwrap();
+        assert_eq!(chip.quirks(), Quirks::schip());
+    }
+
+    #[test]
+    fn shift_uses_vy_with_quirk() {
+        let quirks = Quirks { shift_uses_vy: true, ..Quirks::default() };
+        let mut chip = chip8_with_quirks(&[0x6001, 0x6103, 0x8016], quirks);
+        run(&mut chip, 3);
+        assert_eq!((chip.registers()[0], chip.registers()[0xF]), (0x01, 1));
+    }
+
+    #[test]
+    fn register_indices_are_validated() {
+        assert_eq!(register_index(0xF), Ok(15));
+        assert_eq!(register_index(0x10), Err(Chip8Error::InvalidRegister(0x10)));
+        assert_eq!(Instruction::try_from(0x8FE4), Ok(Instruction::AddReg { x_register: 0xF, y_register: 0xE }));
+        assert_eq!(Instruction::try_from(0xDFF1), Ok(Instruction::Draw { x_register: 0xF, y_register: 0xF, count: 1 }));
+        assert_eq!(Instruction::try_from(0x6F12), Ok(Instruction::SetRegister { register: 0xF, value: 0x12 }));
+    }
+
+    #[test]
+    fn trace_callback_records_events() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut chip = chip8(&[0x6005, 0xA300, 0x1204]);
+        let recorded = Rc::clone(&events);
+        chip.set_trace_callback(Box::new(move |event| recorded.borrow_mut().push(event)));
+        run(&mut chip, 3);
+        assert_eq!(*events.borrow(), [
+            TraceEvent::Execute { pc: 0x200, opcode: 0x6005, instruction: Instruction::SetRegister { register: 0, value: 5 } },
+            TraceEvent::Execute { pc: 0x202, opcode: 0xA300, instruction: Instruction::SetIndex(0x300) },
+            TraceEvent::Execute { pc: 0x204, opcode: 0x1204, instruction: Instruction::Jump(0x204) },
+        ]);
+        assert_eq!(events.borrow()[0].to_string(), "0x0200:   0x6005   -   SET V0 0x05");
+    }
+
+    #[test]
+    fn jump_to_self_halts() {
+        let mut chip = chip8(&[0x6001, 0x1202]);
+        chip.set_ticks(10);
+        assert_eq!(chip.update(), Ok(StepOutcome::Halted));
+        assert!(chip.is_halted());
+        assert_eq!(chip.cycles(), 2);
+        assert_eq!(chip.update(), Ok(StepOutcome::Halted));
+        assert_eq!((chip.cycles(), chip.program_counter()), (2, 0x202));
+    }
+
+    #[test]
+    fn seventeen_nested_calls_overflow_the_stack() {
+        let program: Vec<u16> = (0..17).map(|i| 0x2202 + i * 2).collect();
+        let mut chip = chip8(&program);
+        run(&mut chip, 16);
+        assert_eq!(chip.stack().len(), STACK_DEPTH);
+        assert_eq!(chip.step(), Err(Chip8Error::StackOverflow));
+    }
+
+    #[derive(Default)]
+    struct RecordingAudio {
+        transitions: Vec<bool>,
+    }
+
+    impl Audio for RecordingAudio {
+        fn set_playing(&mut self, playing: bool) {
+            if self.transitions.last() != Some(&playing) {
+                self.transitions.push(playing);
+            }
+        }
+    }
+
+    #[test]
+    fn render_audio_follows_the_sound_timer() {
+        let mut chip = chip8(&[0x6002, 0xF018, 0x1204]);
+        let mut audio = RecordingAudio::default();
+        chip.render_audio(&mut audio);
+        run(&mut chip, 2);
+        for _ in 0..4 {
+            chip.render_audio(&mut audio);
+            chip.tick_timers();
+        }
+        assert_eq!(audio.transitions, [false, true, false]);
+    }
+
+    #[test]
+    fn turned_off_pixels_fade_out() {
+        let mut chip = chip8(&[0xA20A, 0xD011, 0xD011, 0x1206, 0x0000, 0x8000]);
+        chip.set_fade(true);
+        run(&mut chip, 2);
+        assert_eq!(chip.screen_brightness()[0], u8::MAX);
+        run(&mut chip, 1);
+        assert_eq!(chip.screen()[0], 0);
+        let mut previous = chip.screen_brightness()[0];
+        assert_eq!(previous, u8::MAX);
+        for _ in 0..4 {
+            chip.tick_timers();
+            let brightness = chip.screen_brightness()[0];
+            assert!(brightness < previous);
+            previous = brightness;
+        }
+        chip.tick_timers();
+        assert_eq!(chip.screen_brightness()[0], 0);
+    }
+
+    #[test]
+    fn pixels_turn_dark_instantly_without_fade() {
+        let mut chip = chip8(&[0xA20A, 0xD011, 0xD011, 0x1206, 0x0000, 0x8000]);
+        run(&mut chip, 3);
+        assert_eq!(chip.screen_brightness()[0], 0);
+    }
+
+    #[test]
+    fn draws_to_selected_planes() {
+        // the sprites are one row each: 0xC0 for the first and 0x60 for the second plane
+        let mut chip = chip8(&[0xA210, 0xD011, 0xF201, 0xA211, 0xD011, 0xF301, 0xA212, 0xD011, 0xC060, 0xF0F0]);
+        run(&mut chip, 2);
+        assert_eq!(chip.screen()[..4], [1, 1, 0, 0]);
+        run(&mut chip, 3);
+        assert_eq!(chip.screen()[..4], [1, 3, 2, 0]);
+        run(&mut chip, 3);
+        assert_eq!(chip.screen()[..4], [2, 0, 1, 3]);
+        assert_eq!(chip.render_ascii()[..10], *"█ ██");
+    }
+
+    #[test]
+    fn load_program_checks_the_size() {
+        let mut chip = Chip8::new(1, false).unwrap();
+        assert_eq!(chip.load_program(&[]), Err(Chip8Error::EmptyProgram));
+        assert_eq!(chip.load_program(&[0xAA; 3584]), Ok(()));
+        assert_eq!(chip.peek_memory(0xFFF), Some(0xAA));
+        assert_eq!(chip.load_program(&[0; 3585]), Err(Chip8Error::ProgramTooLarge { size: 3585, max: 3584 }));
+        assert_eq!(
+            chip.load_program_at(ETI_660_PROGRAM_START, &[0; 2561]),
+            Err(Chip8Error::ProgramTooLarge { size: 2561, max: 2560 })
+        );
+    }
+
+    #[test]
+    fn instructions_decode_the_loaded_program() {
+        let chip = ibm_logo();
+        let first: Vec<_> = chip.instructions().take(4).collect();
+        assert_eq!(first, [
+            (0x200, Ok(Instruction::ClearScreen)),
+            (0x202, Ok(Instruction::SetIndex(0x22A))),
+            (0x204, Ok(Instruction::SetRegister { register: 0, value: 0x0C })),
+            (0x206, Ok(Instruction::SetRegister { register: 1, value: 0x08 })),
+        ]);
+        assert_eq!(chip.instructions().count(), IBM_LOGO.len() / 2);
+        assert_eq!(chip.instructions().last(), Some((0x282, Ok(Instruction::ClearScreen))));
+    }
+
+    #[test]
+    fn loads_eti_660_programs_at_0x600() {
+        let mut chip = Chip8::new(1, false).unwrap();
+        chip.load_program_at(ETI_660_PROGRAM_START, &words(&[0x6007, 0x1602])).unwrap();
+        assert_eq!(chip.program_counter(), 0x600);
+        assert_eq!(chip.dump_memory(0x600..0x604).unwrap(), [0x60, 0x07, 0x16, 0x02]);
+        assert_eq!(chip.peek_memory(0x200), Some(0));
+        run(&mut chip, 2);
+        assert_eq!(chip.registers()[0], 7);
+        assert!(chip.is_halted());
+        assert_eq!(chip.load_program_at(0x1000, &[0]), Err(Chip8Error::OutOfBounds { addr: 0x1000, size: RAM_SIZE }));
+    }
+
+    #[test]
+    fn load_program_at_rejects_odd_or_low_origins() {
+        let mut chip = ibm_logo();
+        assert_eq!(chip.load_program_at(0x1FE, &[0x12, 0x00]), Err(Chip8Error::InvalidOrigin(0x1FE)));
+        assert_eq!(chip.load_program_at(0x000, &[0x12, 0x00]), Err(Chip8Error::InvalidOrigin(0x000)));
+        assert_eq!(chip.load_program_at(0x601, &[0x12, 0x00]), Err(Chip8Error::InvalidOrigin(0x601)));
+        assert_eq!(chip.font_bytes(), FONT);
+        assert_eq!(chip.dump_memory(0x200..0x202).unwrap(), IBM_LOGO[..2]);
+
+        chip.load_program_at(0x210, &[0x12, 0x10]).unwrap();
+        assert_eq!(chip.dump_memory(0x200..0x202).unwrap(), IBM_LOGO[..2]);
+        assert_eq!(chip.dump_memory(0x210..0x214).unwrap(), [0x12, 0x10, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn display_hash_changes_with_a_single_pixel() {
+        let mut first = ibm_logo();
+        let mut second = ibm_logo();
+        first.run_frames(3).unwrap();
+        second.run_frames(3).unwrap();
+        assert_eq!(first.display_hash(), second.display_hash());
+        light(&mut second, &[(0, 0)]);
+        assert_ne!(first.display_hash(), second.display_hash());
+        assert_ne!(Chip8::new(1, false).unwrap().display_hash(), first.display_hash());
+    }
+
+    #[test]
+    fn screen_packed_is_msb_first() {
+        let mut chip = chip8(&[0x1200]);
+        light(&mut chip, &[(0, 0), (7, 0), (9, 0), (63, 31)]);
+        let packed = chip.screen_packed();
+        assert_eq!(packed[..2], [0b1000_0001, 0b0100_0000]);
+        assert_eq!(packed[LOWRES_WIDTH * LOWRES_HEIGHT / 8 - 1], 0b0000_0001);
+        assert_eq!(packed.iter().map(|byte| byte.count_ones()).sum::<u32>(), 4);
+    }
+
+    #[test]
+    fn export_ppm_header_and_length() {
+        let mut chip = chip8(&[0x1200]);
+        light(&mut chip, &[(0, 0)]);
+        let ppm = chip.export_ppm(2);
+        let header = b"P6\n128 64\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 128 * 64 * 3);
+        assert_eq!(ppm[header.len()..header.len() + 9], [255, 255, 255, 255, 255, 255, 0, 0, 0]);
+
+        let ppm = chip.export_ppm_with_colors(1, (1, 2, 3), (4, 5, 6));
+        let header = b"P6\n64 32\n255\n";
+        assert_eq!(ppm[header.len()..header.len() + 6], [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn palette_maps_plane_bits_to_colors() {
+        let palette = Palette { bg: (0, 0, 0), fg: (1, 1, 1), fg2: (2, 2, 2), blend: (3, 3, 3) };
+        assert_eq!([0, 1, 2, 3].map(|index| palette.color(index)), [palette.bg, palette.fg, palette.fg2, palette.blend]);
+        assert_eq!(Palette::default(), Palette::classic());
+
+        let mut chip = chip8(&[0xF301, 0xA208, 0xD011, 0x1206, 0x8080]);
+        run(&mut chip, 3);
+        let mut screen = RecordingScreen::default();
+        chip.render(&mut screen, &palette);
+        assert_eq!(screen.draws, [(0, 0, palette.blend)]);
+    }
+
+    #[test]
+    fn display_wait_stops_update_after_a_draw() {
+        let program = [0xA300, 0xD011, 0x7001, 0xD011, 0x7001, 0x120A];
+        let quirks = Quirks { display_wait: true, ..Quirks::default() };
+        let mut chip = chip8_with_quirks(&program, quirks);
+        chip.set_ticks(100);
+        chip.update().unwrap();
+        assert_eq!((chip.cycles(), chip.program_counter()), (2, 0x204));
+        chip.update().unwrap();
+        assert_eq!((chip.cycles(), chip.program_counter()), (4, 0x208));
+
+        let mut chip = chip8(&program);
+        chip.set_ticks(100);
+        assert_eq!(chip.update(), Ok(StepOutcome::Halted));
+    }
+
+    #[test]
+    fn trace_history_keeps_the_latest_entries() {
+        let mut chip = chip8(&[0x7001, 0x1200]);
+        assert_eq!(chip.recent_trace().count(), 0);
+        chip.set_trace_history(true);
+        run(&mut chip, TRACE_HISTORY_SIZE + 10);
+        let trace: Vec<_> = chip.recent_trace().collect();
+        assert_eq!(trace.len(), TRACE_HISTORY_SIZE);
+        assert_eq!(trace[..2], [(0x200, 0x7001), (0x202, 0x1200)]);
+        assert_eq!(trace.last(), Some(&(0x202, 0x1200)));
+    }
+
+    #[test]
+    fn collision_flag_only_for_erased_visible_pixels() {
+        // the second sprite straddles the right edge, its clipped columns would wrap onto the first one
+        let mut chip = chip8(&[0x6000, 0x6100, 0xA212, 0xD011, 0x603C, 0xA213, 0xD011, 0x603F, 0xD011, 0xF0FF]);
+        run(&mut chip, 4);
+        assert_eq!(chip.registers()[0xF], 0);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0xF], 0);
+        assert_eq!(lit_pixels(&chip), [(0, 0), (1, 0), (2, 0), (3, 0), (60, 0), (61, 0), (62, 0), (63, 0)]);
+        run(&mut chip, 2);
+        assert_eq!(chip.registers()[0xF], 1);
+        assert_eq!(lit_pixels(&chip), [(0, 0), (1, 0), (2, 0), (3, 0), (60, 0), (61, 0), (62, 0)]);
+    }
+
+    #[test]
+    fn queued_input_applies_at_its_frame() {
+        let mut chip = chip8(&[0xE09E, 0x1200, 0x1204]);
+        chip.set_ticks(2);
+        chip.queue_input(3, 0x0, true);
+        for frame in 0..3 {
+            assert_eq!(chip.frame_counter(), frame);
+            assert_eq!(chip.update(), Ok(StepOutcome::Continued));
+            assert_eq!(chip.program_counter(), 0x200);
+        }
+        assert_eq!(chip.update(), Ok(StepOutcome::Halted));
+        assert_eq!(chip.program_counter(), 0x204);
+        assert_eq!(chip.frame_counter(), 4);
+    }
+
+    #[test]
+    fn builder_configures_everything() {
+        let quirks = Quirks::schip();
+        let mut chip = Chip8::builder()
+            .ticks(7)
+            .debug(false)
+            .seed(9)
+            .quirks(quirks)
+            .memory_size(XO_CHIP_RAM_SIZE)
+            .clock_hz(1000)
+            .lenient(true)
+            .build()
+            .unwrap();
+        assert_eq!((chip.ticks(), chip.quirks(), chip.clock_hz()), (7, quirks, 1000));
+        assert_eq!(chip.peek_memory(0xFFFF), Some(0));
+        chip.load_program(&words(&[0xFFFF, 0xC0FF])).unwrap();
+        run(&mut chip, 2);
+        let mut same_seed = Chip8::builder().seed(9).build().unwrap();
+        same_seed.load_program(&words(&[0xC0FF])).unwrap();
+        run(&mut same_seed, 1);
+        assert_eq!(chip.registers()[0], same_seed.registers()[0]);
+        assert_eq!(Chip8::builder().memory_size(100).build().err(), Some(Chip8Error::InvalidMemorySize(100)));
+    }
+
+    #[test]
+    fn fetch_rejects_pc_past_memory_or_odd() {
+        let mut chip = chip8(&[0x1FFE]);
+        chip.poke_memory(0xFFF, 0xE0).unwrap();
+        run(&mut chip, 2);
+        assert_eq!(chip.step(), Err(Chip8Error::PcOutOfBounds(0x1000)));
+        assert_eq!(chip.step(), Err(Chip8Error::PcOutOfBounds(0x1000)));
+
+        let mut chip = chip8(&[0x1203]);
+        run(&mut chip, 1);
+        assert_eq!(chip.step(), Err(Chip8Error::PcOutOfBounds(0x203)));
+        assert_eq!(chip.program_counter(), 0x203);
+    }
+
+    #[test]
+    fn fixed_size_stack() {
+        let mut stack = Stack::new();
+        assert_eq!(stack.pop(), Err(Chip8Error::StackUnderflow));
+        for address in 0..STACK_DEPTH as u16 {
+            stack.push(address).unwrap();
+        }
+        assert_eq!(stack.push(0xFFF), Err(Chip8Error::StackOverflow));
+        assert_eq!(stack.as_slice().len(), STACK_DEPTH);
+        assert_eq!(stack.pop(), Ok(STACK_DEPTH as u16 - 1));
+        stack.clear();
+        assert!(stack.as_slice().is_empty());
+    }
+
+    #[test]
+    fn rpl_flags_round_trip() {
+        let mut chip = chip8(&[0x6011, 0x6122, 0x6233, 0xF275, 0x6000, 0x6100, 0x6200, 0xF185]);
+        run(&mut chip, 8);
+        assert_eq!(chip.registers()[..3], [0x11, 0x22, 0x00]);
+    }
+
+    #[test]
+    fn rpl_flags_clamp_to_eight_registers() {
+        let mut program: Vec<u16> = (0..16).map(|register| 0x6001 | register << 8).collect();
+        program.push(0xFF75);
+        program.extend((0..16).map(|register| 0x6000 | register << 8));
+        program.push(0xFF85);
+        let mut chip = chip8(&program);
+        run(&mut chip, program.len());
+        assert_eq!(chip.registers()[..8], [1; 8]);
+        assert_eq!(chip.registers()[8..], [0; 8]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn load_program_file_reads_a_fixture() {
+        let mut chip = Chip8::new(1, false).unwrap();
+        chip.load_program_file(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/roms/IBM Logo.ch8")).unwrap();
+        assert_eq!(chip.dump_memory(0x200..0x200 + IBM_LOGO.len() as u16).unwrap(), IBM_LOGO);
+
+        let err = chip.load_program_file("does/not/exist.ch8").unwrap_err();
+        assert!(matches!(err, Chip8Error::Io(message) if message.starts_with("does/not/exist.ch8: ")));
+    }
+
+    #[test]
+    fn just_pressed_distinguishes_held_from_new_keys() {
+        let mut chip = chip8(&[0x1200]);
+        chip.on_input(0x4, true);
+        assert!(chip.just_pressed(0x4));
+        chip.tick_timers();
+        assert!(!chip.just_pressed(0x4));
+        assert!(chip.key_down(0x4));
+
+        chip.on_input(0x5, true);
+        assert!(chip.just_pressed(0x5));
+        assert!(!chip.just_pressed(0x4));
+        assert!(!chip.just_pressed(0x10));
+    }
+
+    #[test]
+    fn wait_for_key_ignores_a_held_key_until_released() {
+        let mut chip = chip8(&[0xF00A, 0x1202]);
+        chip.on_input(0x7, true);
+        chip.tick_timers();
+        for _ in 0..3 {
+            chip.step().unwrap();
+            chip.tick_timers();
+        }
+        assert_eq!(chip.program_counter(), 0x200);
+
+        chip.on_input(0x7, false);
+        chip.step().unwrap();
+        assert_eq!(chip.registers()[0], 0x7);
+        assert_eq!(chip.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn render_ascii_draws_a_font_sprite() {
+        let mut chip = chip8(&[0x6000, 0xF029, 0xD005]);
+        run(&mut chip, 3);
+        let text = chip.render_ascii();
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows.len(), LOWRES_HEIGHT);
+        assert!(rows.iter().all(|row| row.chars().count() == LOWRES_WIDTH));
+        assert!(text.ends_with('\n'));
+
+        let glyph: Vec<&str> = rows[..6].iter().map(|row| row.trim_end()).collect();
+        assert_eq!(glyph, ["████", "█  █", "█  █", "█  █", "████", ""]);
+        assert!(rows[6..].iter().all(|row| row.trim().is_empty()));
+    }
+
+    #[test]
+    fn draw_rejects_a_sprite_past_memory() {
+        let mut chip = chip8(&[0xAFFA, 0xD01F]);
+        chip.step().unwrap();
+        assert_eq!(chip.step(), Err(Chip8Error::OutOfBounds { addr: 0x1008, size: RAM_SIZE }));
+        assert!(chip.screen().iter().all(|color| *color == 0));
+
+        // a sprite ending on the last byte still fits
+        let mut chip = chip8(&[0xAFFA, 0xD016]);
+        run(&mut chip, 2);
+    }
+
+    #[test]
+    fn sys_call_is_a_no_op() {
+        let mut chip = chip8(&[0x0123, 0x6007]);
+        assert_eq!(Instruction::try_from(0x0123), Ok(Instruction::SysCall(0x123)));
+        assert_eq!(chip.step(), Ok(StepOutcome::Continued));
+        assert_eq!(chip.program_counter(), 0x202);
+        chip.step().unwrap();
+        assert_eq!(chip.registers()[0], 7);
+    }
+
+    #[test]
+    fn xo_chip_memory_holds_programs_past_4k() {
+        let mut chip = Chip8::builder().memory_size(XO_CHIP_RAM_SIZE).build().unwrap();
+        let mut rom = vec![0; 0x1000];
+        rom.push(0xAB);
+        chip.load_program(&rom).unwrap();
+        assert_eq!(chip.peek_memory(0x1200), Some(0xAB));
+        chip.load_program_at(0xF000, &[0xCD]).unwrap();
+        assert_eq!(chip.peek_memory(0xF000), Some(0xCD));
+        assert_eq!(chip.load_program_at(0xFFFE, &[1, 2, 3]), Err(Chip8Error::ProgramTooLarge { size: 3, max: 2 }));
+
+        assert!(Chip8::new(1, false).unwrap().load_program(&rom).is_err());
+        assert_eq!(Chip8::builder().memory_size(RAM_SIZE - 1).build().err(), Some(Chip8Error::InvalidMemorySize(RAM_SIZE - 1)));
+    }
+
+    #[test]
+    fn add_to_index_flags_leaving_12_bits_in_large_memory() {
+        let quirks = Quirks { index_overflow_sets_flag: true, ..Quirks::default() };
+        let mut chip = Chip8::builder().memory_size(XO_CHIP_RAM_SIZE).quirks(quirks).build().unwrap();
+        chip.load_program(&words(&[0xAFFE, 0x6001, 0xF01E, 0xF01E])).unwrap();
+        run(&mut chip, 3);
+        assert_eq!((chip.index(), chip.registers()[0xF]), (0x0FFF, 0));
+        run(&mut chip, 1);
+        assert_eq!((chip.index(), chip.registers()[0xF]), (0x1000, 1));
+    }
+
+    #[test]
+    fn set_index_long_reads_the_next_word() {
+        let mut chip = Chip8::builder().memory_size(XO_CHIP_RAM_SIZE).build().unwrap();
+        chip.load_program(&[0xF0, 0x00, 0x12, 0x34]).unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.index(), 0x1234);
+        assert_eq!(chip.program_counter(), 0x204);
+    }
+
+    #[test]
+    fn skips_step_over_set_index_long() {
+        let mut chip = chip8(&[0x3000, 0xF000, 0x1234, 0x6001]);
+        run(&mut chip, 2);
+        assert_eq!(chip.program_counter(), 0x208);
+        assert_eq!((chip.index(), chip.registers()[0]), (0, 1));
+    }
+
+    #[test]
+    fn pause_freezes_the_cpu_and_timers() {
+        let mut chip = chip8(&[0x6030, 0xF015, 0xF018, 0x7001, 0x1206]);
+        run(&mut chip, 3);
+        chip.set_paused(true);
+        assert!(chip.is_paused());
+        for _ in 0..5 {
+            assert_eq!(chip.update(), Ok(StepOutcome::Continued));
+            chip.tick_timers();
+        }
+        assert_eq!(chip.program_counter(), 0x206);
+        assert_eq!((chip.delay_timer(), chip.sound_timer()), (0x30, 0x30));
+
+        chip.set_paused(false);
+        chip.update().unwrap();
+        chip.tick_timers();
+        assert_eq!(chip.program_counter(), 0x208);
+        assert_eq!((chip.delay_timer(), chip.sound_timer()), (0x2F, 0x2F));
+    }
+
+    #[test]
+    fn rewind_restores_previous_frames() {
+        let mut chip = chip8(&[0x7001, 0xA000, 0xF01E, 0x1200]);
+        assert!(!chip.rewind());
+        chip.set_rewind_capacity(3);
+        let state = |chip: &Chip8| (chip.program_counter(), chip.index(), *chip.registers(), chip.frame_counter());
+        let mut states = Vec::new();
+        for _ in 0..6 {
+            states.push(state(&chip));
+            chip.update().unwrap();
+        }
+
+        assert!(chip.rewind());
+        assert!(chip.rewind());
+        assert_eq!(state(&chip), states[4]);
+        assert!(chip.rewind());
+        assert_eq!(state(&chip), states[3]);
+        assert!(!chip.rewind());
+    }
+
+    #[test]
+    fn opcode_stats_count_executed_instructions() {
+        let mut chip = chip8(&[0x6005, 0x70FF, 0x3000, 0x1202, 0x1208]);
+        assert_eq!(chip.opcode_stats(), None);
+        chip.set_opcode_stats(true);
+        run(&mut chip, 15);
+        let stats = chip.opcode_stats().unwrap();
+        assert_eq!(stats.iter().map(|(name, count)| (*name, *count)).collect::<Vec<_>>(), [
+            ("AddRegister", 5),
+            ("Jump", 4),
+            ("SetRegister", 1),
+            ("SkipEqVal", 5),
+        ]);
+        assert_eq!(chip.program_counter(), 0x208);
+    }
+
+    #[test]
+    fn get_bits_is_msb_first() {
+        assert_eq!(get_bits(0x80), [true, false, false, false, false, false, false, false]);
+        assert_eq!(get_bits(0x01), [false, false, false, false, false, false, false, true]);
+
+        let mut chip = chip8(&[0xA300, 0xD011]);
+        chip.poke_memory(0x300, 0x80).unwrap();
+        run(&mut chip, 2);
+        assert_eq!(lit_pixels(&chip), [(0, 0)]);
+    }
+
+    #[test]
+    fn custom_font_is_used_by_font_instruction() {
+        let font: Vec<u8> = (0..FONT_LEN as u8).map(|byte| byte | 0x80).collect();
+        let mut chip = chip8(&[0x6003, 0xF029, 0xD125]);
+        chip.set_font(&font).unwrap();
+        assert_eq!(chip.font_bytes(), font);
+        run(&mut chip, 2);
+        assert_eq!(chip.index(), FONT_BASE + 3 * 5);
+        assert_eq!(chip.dump_memory(chip.index()..chip.index() + 5).unwrap(), font[15..20]);
+        run(&mut chip, 1);
+        let expected: Vec<(usize, usize)> = (0..5)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .filter(|(x, y)| font[15 + y] & (0x80 >> x) != 0)
+            .collect();
+        let mut lit = lit_pixels(&chip);
+        lit.sort_by_key(|(x, y)| (*y, *x));
+        assert_eq!(lit, expected);
+        assert_eq!(chip.set_font(&font[1..]), Err(Chip8Error::InvalidFont { len: FONT_LEN - 1, expected: FONT_LEN }));
+    }
+
+    #[test]
+    fn big_font_characters() {
+        let mut chip = chip8(&[0x1200]);
+        assert_eq!(chip.dump_memory(BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + 160).unwrap(), BIG_FONT);
+        for character in 0..=0xF {
+            chip.set_register(0, character | 0xF0).unwrap();
+            chip.execute_instruction(Instruction::SetIndexToBigFont { register: 0 }).unwrap();
+            let expected = BIG_FONT_ADDRESS + character as u16 * BIG_FONT_CHAR_SIZE;
+            assert_eq!(chip.index(), expected, "character {character:X}");
+        }
+        let glyphs: Vec<_> = BIG_FONT.chunks(BIG_FONT_CHAR_SIZE as usize).collect();
+        assert!((1..glyphs.len()).all(|i| !glyphs[..i].contains(&glyphs[i])));
+    }
+
+    #[test]
+    fn display_callback_fires_once_per_change() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let mut chip = chip8(&[0x6000, 0xF029, 0xD005, 0x1206]);
+        let recorded = Rc::clone(&frames);
+        chip.on_display_changed(Box::new(move |pixels| recorded.borrow_mut().push(pixels.iter().filter(|color| **color != 0).count())));
+        for _ in 0..10 {
+            chip.update().unwrap();
+            chip.tick_timers();
+        }
+        assert_eq!(*frames.borrow(), [14]);
+    }
+
+    #[test]
+    fn shorter_program_leaves_no_stale_bytes() {
+        let mut chip = chip8(&[0x6001, 0x6101, 0x6201, 0x6301]);
+        run(&mut chip, 4);
+        chip.load_program(&words(&[0x6005])).unwrap();
+        chip.reset();
+        run(&mut chip, 4);
+        assert_eq!(chip.registers()[..4], [5, 0, 0, 0]);
+        assert_eq!(chip.dump_memory(0x202..0x208).unwrap(), [0; 6]);
+    }
+
+    #[test]
+    fn unload_keeps_the_font() {
+        let mut chip = chip8(&[0x6001, 0xA300, 0x1204]);
+        run(&mut chip, 3);
+        chip.unload();
+        assert_eq!(chip.dump_memory(0x200..0x206).unwrap(), [0; 6]);
+        assert_eq!(chip.font_bytes(), FONT);
+        assert_eq!((chip.program_counter(), chip.index(), chip.registers()[0]), (0x200, 0, 0));
+        assert!(!chip.is_halted());
+    }
+
+    #[test]
+    fn cycles_count_executed_instructions() {
+        let mut chip = chip8(&[0x7001, 0x1200]);
+        chip.set_ticks(7);
+        assert_eq!(chip.cycles(), 0);
+        chip.update().unwrap();
+        assert_eq!(chip.cycles(), 7);
+        assert_eq!(chip.run_cycles(5), Ok(StepOutcome::Continued));
+        assert_eq!(chip.cycles(), 12);
+        assert_eq!(chip.registers()[0], 6);
+        assert_eq!(chip.program_counter(), 0x200);
+    }
+
+    #[test]
+    fn breakpoint_stops_before_the_instruction() {
+        let mut chip = chip8(&[0x6001, 0x6102, 0x6203, 0x1206]);
+        chip.set_ticks(10);
+        chip.add_breakpoint(0x204);
+        assert_eq!(chip.update(), Ok(StepOutcome::Breakpoint(0x204)));
+        assert_eq!(chip.program_counter(), 0x204);
+        assert_eq!(chip.registers()[..3], [1, 2, 0]);
+        assert_eq!(chip.cycles(), 2);
+
+        // continuing executes the instruction under the breakpoint
+        assert_eq!(chip.step(), Ok(StepOutcome::Continued));
+        assert_eq!(chip.registers()[2], 3);
+
+        chip.reset();
+        assert_eq!(chip.run_frames(3), Ok(StepOutcome::Breakpoint(0x204)));
+        chip.reset();
+        assert_eq!(chip.run_until(Chip8::is_halted, 10), Ok(false));
+        assert_eq!(chip.program_counter(), 0x204);
+
+        chip.remove_breakpoint(0x204);
+        assert_eq!(chip.run_until(Chip8::is_halted, 10), Ok(true));
+
+        // a reset back onto a breakpoint stops there again
+        chip.add_breakpoint(0x200);
+        chip.reset();
+        assert_eq!(chip.step(), Ok(StepOutcome::Breakpoint(0x200)));
+        chip.reset();
+        assert_eq!(chip.step(), Ok(StepOutcome::Breakpoint(0x200)));
+    }
+
+    #[test]
+    fn step_outcomes() {
+        let mut chip = chip8(&[0x6001, 0xF00A, 0x1204, 0xFFFF]);
+        assert_eq!(chip.step(), Ok(StepOutcome::Continued));
+        assert_eq!(chip.step(), Ok(StepOutcome::AwaitingKey));
+        chip.on_input(0x2, true);
+        chip.tick_timers();
+        chip.on_input(0x2, false);
+        assert_eq!(chip.step(), Ok(StepOutcome::Continued));
+        assert_eq!(chip.step(), Ok(StepOutcome::Halted));
+
+        let mut chip = chip8(&[0x6001, 0x1202]);
+        chip.set_ticks(2);
+        chip.add_breakpoint(0x202);
+        assert_eq!(chip.update(), Ok(StepOutcome::Breakpoint(0x202)));
+
+        let mut chip = chip8(&[0xFFFF]);
+        assert_eq!(chip.step(), Err(Chip8Error::UnknownInstruction(0xFFFF)));
+    }
+
+    #[test]
+    fn skip_instructions_decode() {
+        assert_eq!(Instruction::try_from(0x3A42), Ok(Instruction::SkipEqVal { register: 0xA, value: 0x42 }));
+        assert_eq!(Instruction::try_from(0x4A42), Ok(Instruction::SkipNeVal { register: 0xA, value: 0x42 }));
+        assert_eq!(Instruction::try_from(0x5AB0), Ok(Instruction::SkipEqReg { x_register: 0xA, y_register: 0xB }));
+        assert_eq!(Instruction::try_from(0x9AB0), Ok(Instruction::SkipNeReg { x_register: 0xA, y_register: 0xB }));
+        assert_eq!(Instruction::try_from(0x5AB1), Err(Chip8Error::UnknownInstruction(0x5AB1)));
+        assert_eq!(Instruction::try_from(0x9AB1), Err(Chip8Error::UnknownInstruction(0x9AB1)));
+    }
+
+    #[test]
+    fn skip_instructions_compare() {
+        // each case sets V0 = 5 and V1 = 5 or 6, then runs the skip
+        let cases = [
+            (0x3005, 5, true),
+            (0x3006, 5, false),
+            (0x4005, 5, false),
+            (0x4006, 5, true),
+            (0x5010, 5, true),
+            (0x5010, 6, false),
+            (0x9010, 5, false),
+            (0x9010, 6, true),
+        ];
+        for (skip, v1, skipped) in cases {
+            let mut chip = chip8(&[0x6005, 0x6100 | v1, skip]);
+            run(&mut chip, 3);
+            let expected = if skipped { 0x208 } else { 0x206 };
+            assert_eq!(chip.program_counter(), expected, "{skip:#06x} with V1 = {v1}");
+        }
+    }
+
+    #[test]
+    fn display_snapshot_round_trip() {
+        let mut chip = chip8(&[0x6000, 0xF029, 0xD005, 0x00E0]);
+        run(&mut chip, 3);
+        let screen = chip.screen().to_vec();
+        let snapshot = chip.display_snapshot();
+        assert_eq!(snapshot, chip.screen_packed()[..LOWRES_WIDTH * LOWRES_HEIGHT / 8]);
+
+        run(&mut chip, 1);
+        assert!(lit_pixels(&chip).is_empty());
+        chip.restore_display(&snapshot).unwrap();
+        assert_eq!(chip.screen(), screen);
+        assert_eq!(chip.restore_display(&snapshot[1..]), Err(Chip8Error::InvalidState));
+    }
+
+    #[test]
+    fn held_key_completes_wait_for_key_once() {
+        let mut chip = chip8(&[0xF00A, 0x7101, 0x1200]);
+        chip.set_ticks(10);
+        chip.on_input(0x9, true);
+        for _ in 0..30 {
+            chip.update().unwrap();
+            chip.tick_timers();
+        }
+        assert_eq!(chip.registers()[1], 0);
+
+        chip.on_input(0x9, false);
+        for _ in 0..30 {
+            chip.update().unwrap();
+            chip.tick_timers();
+        }
+        assert_eq!(chip.registers()[..2], [0x9, 1]);
+    }
+
+    #[test]
+    fn wait_key_repeats_for_a_held_key() {
+        let quirks = Quirks { wait_key_repeats: true, ..Quirks::default() };
+        let mut chip = chip8_with_quirks(&[0xF00A, 0x7101, 0x1200], quirks);
+        chip.on_input(0x9, true);
+        run(&mut chip, 9);
+        assert_eq!(chip.registers()[..2], [0x9, 3]);
+    }
+
+    #[test]
+    fn font_bytes_match_the_builtin_font() {
+        let chip = chip8(&[0x1200]);
+        assert_eq!(chip.font_bytes(), FONT);
+        assert_eq!(FONT_LEN, 80);
+        assert_eq!(chip.peek_memory(FONT_BASE), Some(0xF0));
+        assert_eq!(chip.dump_memory(FONT_BASE..FONT_BASE + FONT_LEN as u16).unwrap(), FONT);
+    }
+
+    #[test]
+    fn skips_at_the_top_of_memory_fail_cleanly() {
+        let mut chip = chip8(&[0x1FFE]);
+        chip.poke_memory(0xFFE, 0x30).unwrap();
+        run(&mut chip, 2);
+        assert_eq!(chip.program_counter(), 0x1002);
+        assert_eq!(chip.step(), Err(Chip8Error::PcOutOfBounds(0x1002)));
+
+        let mut chip = Chip8::builder().memory_size(XO_CHIP_RAM_SIZE).build().unwrap();
+        chip.load_program_at(0xFFFC, &[0x30, 0x00]).unwrap();
+        assert_eq!(chip.step(), Err(Chip8Error::PcOutOfBounds(0xFFFE)));
+    }
+
+    #[test]
+    fn fast_forward_reports_the_display_once() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let calls = Rc::new(RefCell::new(0));
+        // draws and erases the 0 glyph every other frame, ending with it drawn
+        let mut chip = chip8(&[0x6000, 0xF029, 0xD005, 0x1204]);
+        let counter = Rc::clone(&calls);
+        chip.on_display_changed(Box::new(move |_| *counter.borrow_mut() += 1));
+        chip.fast_forward(20).unwrap();
+        assert_eq!(*calls.borrow(), 1);
+        assert_eq!(chip.frame_counter(), 20);
+        assert_eq!(lit_pixels(&chip).len(), 14);
+        assert_eq!(chip.take_dirty().len(), LOWRES_WIDTH * LOWRES_HEIGHT);
+
+        chip.update().unwrap();
+        assert_eq!(*calls.borrow(), 2);
+    }
+
+    #[test]
+    fn draw_start_wraps_at_the_current_resolution() {
+        let mut chip = chip8(&[0xA300, 0x6041, 0x6122, 0xD011]);
+        chip.poke_memory(0x300, 0x80).unwrap();
+        run(&mut chip, 4);
+        assert_eq!(lit_pixels(&chip), [(1, 2)]);
+
+        let mut chip = chip8(&[0x00FF, 0xA300, 0x6041, 0x6122, 0xD011]);
+        chip.poke_memory(0x300, 0x80).unwrap();
+        run(&mut chip, 5);
+        assert_eq!(lit_pixels(&chip), [(65, 34)]);
+
+        let mut chip = chip8(&[0x00FF, 0xA300, 0x6081, 0x6142, 0xD011]);
+        chip.poke_memory(0x300, 0x80).unwrap();
+        run(&mut chip, 5);
+        assert_eq!(lit_pixels(&chip), [(1, 2)]);
+    }
+
+    #[test]
+    fn execute_instruction_runs_without_memory() {
+        let mut chip = chip8(&[0x1200]);
+        chip.execute_instruction(Instruction::SetRegister { register: 0xA, value: 0x42 }).unwrap();
+        assert_eq!(chip.registers()[0xA], 0x42);
+        assert_eq!(chip.program_counter(), 0x202);
+        assert_eq!(chip.execute_instruction(Instruction::Return), Err(Chip8Error::StackUnderflow));
+        assert_eq!(chip.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn execute_instruction_advances_the_program_counter_first() {
+        let mut chip = chip8(&[0x1200]);
+        chip.execute_instruction(Instruction::Jump(0x204)).unwrap();
+        assert_eq!(chip.program_counter(), 0x204);
+        assert!(!chip.is_halted());
+        chip.execute_instruction(Instruction::Jump(0x204)).unwrap();
+        assert!(chip.is_halted());
+
+        let mut chip = chip8(&[0x1200]);
+        chip.execute_instruction(Instruction::WaitForKey { register: 3 }).unwrap();
+        assert_eq!(chip.program_counter(), 0x200);
+        assert!(!chip.is_halted());
+    }
+
+    #[test]
+    fn update_for_runs_at_the_clock_rate() {
+        let mut chip = chip8(&[0x7001, 0x1200]);
+        chip.set_clock_hz(700);
+        let cycles: Vec<u64> = (0..3)
+            .map(|_| {
+                chip.update_for(Duration::from_millis(1)).unwrap();
+                chip.cycles()
+            })
+            .collect();
+        assert_eq!(cycles, [0, 1, 2]);
+
+        for _ in 0..997 {
+            chip.update_for(Duration::from_millis(1)).unwrap();
+        }
+        assert_eq!(chip.cycles(), 700);
+        assert_eq!(chip.frame_counter(), 1000);
+    }
+
+    #[test]
+    fn update_for_behaves_like_a_frame() {
+        let quirks = Quirks { display_wait: true, ..Quirks::default() };
+        let mut chip = chip8_with_quirks(&[0xD005, 0xD005, 0xE19E, 0x1206, 0x1208], quirks);
+        chip.set_clock_hz(600);
+        chip.queue_input(1, 0x0, true);
+        chip.update_for(Duration::from_millis(100)).unwrap();
+        assert_eq!(chip.program_counter(), 0x202);
+        chip.update_for(Duration::from_millis(100)).unwrap();
+        assert_eq!(chip.program_counter(), 0x204);
+        assert_eq!(chip.update_for(Duration::from_millis(100)), Ok(StepOutcome::Halted));
+        assert_eq!(chip.program_counter(), 0x208);
+        assert_eq!(chip.frame_counter(), 3);
+    }
+
+    #[test]
+    fn last_draw_collisions_counts_erased_pixels() {
+        // 0xF0F0 and 0x3C3C overlap in two pixels on each of the two rows
+        let mut chip = chip8(&[0xA300, 0xD012, 0xA302, 0xD012, 0x6108, 0xD112]);
+        for (addr, byte) in (0x300..).zip([0xF0, 0xF0, 0x3C, 0x3C]) {
+            chip.poke_memory(addr, byte).unwrap();
+        }
+        run(&mut chip, 2);
+        assert_eq!(chip.last_draw_collisions(), 0);
+        run(&mut chip, 2);
+        assert_eq!(chip.last_draw_collisions(), 4);
+        assert_eq!(chip.registers()[0xF], 1);
+        run(&mut chip, 2);
+        assert_eq!(chip.last_draw_collisions(), 0);
+        assert_eq!(chip.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn assemble_round_trips_disassemble() {
+        let source = "\
+            CLS\n\
+            SET V0 0x05\n\
+            INDEXL 0x1234\n\
+            DRAW V0 V1 5 ; a comment\n\
+            SE VA VB\n\
+            JUMP 0x200\n\
+            DB 0x12\n";
+        let rom = assemble(source).unwrap();
+        assert_eq!(rom, [0x00, 0xE0, 0x60, 0x05, 0xF0, 0x00, 0x12, 0x34, 0xD0, 0x15, 0x5A, 0xB0, 0x12, 0x00, 0x12]);
+        let listing: Vec<String> = disassemble(&rom).into_iter().map(|(_, text)| text).collect();
+        assert_eq!(listing, ["CLS", "SET V0 0x05", "INDEXL 0x1234", "DRAW V0 V1 5", "SE VA VB", "JUMP 0x200", "DB 0x12"]);
+        assert_eq!(assemble("SET V0 0x05\nSET V0").err(), Some(Chip8Error::InvalidAssembly { line: 2, text: "SET V0".to_string() }));
+    }
+
+    #[test]
+    fn every_opcode_round_trips_through_the_assembler() {
+        for opcode in 0..=u16::MAX {
+            let rom = opcode.to_be_bytes();
+            let source: Vec<String> = disassemble(&rom).into_iter().map(|(_, text)| text).collect();
+            assert_eq!(assemble(&source.join("\n")).unwrap(), rom, "{opcode:#06x}: {source:?}");
+        }
+        assert_eq!(disassemble(&[0xF0, 0x00]), [(0x200, "DB 0xf000".to_string())]);
+        assert_eq!(assemble("SYS 0x123").unwrap(), [0x01, 0x23]);
+        for text in ["SYS 0x0e0", "SYS 0x0ee", "SYS 0x0c1", "SYS 0x1000"] {
+            assert_eq!(assemble(text).err(), Some(Chip8Error::InvalidAssembly { line: 1, text: text.to_string() }));
+        }
+    }
+
+    #[test]
+    fn add_reg_flag_overwrites_vf_operands() {
+        // VX = VF: the carry replaces the sum
+        let mut chip = chip8(&[0x6FFF, 0x6102, 0x8F14]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0xF], 1);
+        let mut chip = chip8(&[0x6F01, 0x6102, 0x8F14]);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0xF], 0);
+
+        // VY = VF: the sum uses VF before it becomes the carry
+        let mut chip = chip8(&[0x60FF, 0x6F02, 0x80F4]);
+        run(&mut chip, 3);
+        assert_eq!((chip.registers()[0], chip.registers()[0xF]), (0x01, 1));
+        let mut chip = chip8(&[0x6010, 0x6F02, 0x80F4]);
+        run(&mut chip, 3);
+        assert_eq!((chip.registers()[0], chip.registers()[0xF]), (0x12, 0));
+    }
+
+    #[test]
+    fn wrap_quirks_per_axis() {
+        let cases = [
+            (false, false, vec![(63, 31)]),
+            (true, false, vec![(0, 31), (63, 31)]),
+            (false, true, vec![(63, 0), (63, 31)]),
+            (true, true, vec![(0, 0), (63, 0), (0, 31), (63, 31)]),
+        ];
+        for (wrap_x, wrap_y, expected) in cases {
+            let quirks = Quirks { wrap_x, wrap_y, ..Quirks::default() };
+            let mut chip = chip8_with_quirks(&[0x603F, 0x611F, 0xA300, 0xD012], quirks);
+            chip.poke_memory(0x300, 0xC0).unwrap();
+            chip.poke_memory(0x301, 0xC0).unwrap();
+            run(&mut chip, 4);
+            let mut lit = lit_pixels(&chip);
+            lit.sort_by_key(|(x, y)| (*y, *x));
+            assert_eq!(lit, expected, "wrap_x {wrap_x}, wrap_y {wrap_y}");
+        }
+    }
+
+    #[test]
+    fn writes_into_the_program_are_traced() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut chip = chip8(&[0xA300, 0xF033, 0xA206, 0xF155, 0x1208]);
+        let recorded = Rc::clone(&events);
+        chip.set_trace_callback(Box::new(move |event| {
+            if let TraceEvent::SelfModify { .. } = event {
+                recorded.borrow_mut().push(event);
+            }
+        }));
+        run(&mut chip, 2);
+        assert!(events.borrow().is_empty());
+        run(&mut chip, 2);
+        assert_eq!(*events.borrow(), [TraceEvent::SelfModify { addr: 0x206 }, TraceEvent::SelfModify { addr: 0x207 }]);
+        assert_eq!(events.borrow()[0].to_string(), "0x0206:   program modified itself");
+    }
+
+    #[test]
+    fn directions_press_the_mapped_keys() {
+        let mut chip = chip8(&[0x1200]);
+        assert_eq!(chip.keymap(), KeyMap::numpad());
+        chip.on_direction(Direction::Up, true);
+        assert!(chip.key_down(0x2));
+
+        chip.set_keymap(KeyMap::wasd());
+        chip.on_direction(Direction::Left, true);
+        chip.on_direction(Direction::Action, true);
+        chip.on_direction(Direction::Action, false);
+        assert!(chip.key_down(0x7));
+        assert!(!chip.key_down(0x6));
+
+        // a press lands on the key the ROM checks with EX9E
+        let mut chip = chip8(&[0x6104, 0xE19E, 0x1202, 0x6001]);
+        chip.on_direction(Direction::Left, true);
+        run(&mut chip, 3);
+        assert_eq!(chip.registers()[0], 1);
+    }
+
+    #[test]
+    fn timer_accessors_show_the_countdown() {
+        let mut chip = chip8(&[0x6003, 0xF015, 0xF018, 0x1206]);
+        assert_eq!((chip.delay_timer(), chip.sound_timer()), (0, 0));
+        run(&mut chip, 3);
+        let countdown: Vec<(u8, u8)> = (0..4)
+            .map(|_| {
+                chip.tick_timers();
+                (chip.delay_timer(), chip.sound_timer())
+            })
+            .collect();
+        assert_eq!(countdown, [(2, 2), (1, 1), (0, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn lenient_mode_skips_unknown_opcodes() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let mut chip = chip8(&[0x6001, 0xFFFF, 0x6102]);
+        run(&mut chip, 1);
+        assert_eq!(chip.step(), Err(Chip8Error::UnknownInstruction(0xFFFF)));
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut chip = chip8(&[0x6001, 0xFFFF, 0x6102]);
+        let recorded = Rc::clone(&events);
+        chip.set_trace_callback(Box::new(move |event| {
+            if let TraceEvent::UnknownInstruction { .. } = event {
+                recorded.borrow_mut().push(event);
+            }
+        }));
+        chip.set_lenient(true);
+        assert_eq!(chip.run_cycles(3), Ok(StepOutcome::Continued));
+        assert_eq!(chip.registers()[..2], [1, 2]);
+        assert_eq!(*events.borrow(), [TraceEvent::UnknownInstruction { pc: 0x202, opcode: 0xFFFF }]);
+        assert_eq!(chip.cycles(), 3);
+    }
+
+    #[test]
+    fn lenient_skips_use_the_frame_budget() {
+        let mut chip = chip8(&[0xFFFF, 0xFFFF, 0xFFFF, 0x7001, 0x1208]);
+        chip.set_lenient(true);
+        chip.set_ticks(3);
+        chip.update().unwrap();
+        assert_eq!((chip.program_counter(), chip.cycles()), (0x206, 3));
+        chip.update().unwrap();
+        assert_eq!(chip.registers()[0], 1);
+    }
+
+    #[test]
+    fn poke_a_sprite_and_draw_it() {
+        let mut chip = chip8(&[0xD015]);
+        for (addr, byte) in (0x400..).zip([0x90, 0x60, 0x60, 0x90, 0x00]) {
+            chip.poke_memory(addr, byte).unwrap();
+        }
+        chip.set_index(0x400);
+        chip.set_register(0, 10).unwrap();
+        chip.set_register(1, 20).unwrap();
+        run(&mut chip, 1);
+        assert_eq!(lit_pixels(&chip), [(10, 20), (13, 20), (11, 21), (12, 21), (11, 22), (12, 22), (10, 23), (13, 23)]);
+
+        assert_eq!(chip.poke_memory(0x1000, 1), Err(Chip8Error::OutOfBounds { addr: 0x1000, size: RAM_SIZE }));
+        assert_eq!(chip.set_register(16, 1), Err(Chip8Error::InvalidRegister(16)));
+    }
+
+    #[test]
+    fn wait_for_key_stores_the_hex_value() {
+        for key in [0x0, 0xA, 0xF] {
+            let mut chip = chip8(&[0x65FF, 0xF50A]);
+            run(&mut chip, 2);
+            chip.on_input(key, true);
+            chip.tick_timers();
+            chip.on_input(key, false);
+            run(&mut chip, 1);
+            assert_eq!(chip.registers()[5], key);
+            assert_eq!(chip.program_counter(), 0x204);
+        }
+    }
+
+    #[test]
+    fn service_runs_cycles_timers_and_sinks() {
+        let mut chip = chip8(&[0x6005, 0xF018, 0x7101, 0x1204]);
+        chip.set_clock_hz(600);
+        let mut screen = RecordingScreen::default();
+        let mut audio = RecordingAudio::default();
+
+        chip.service(Duration::from_millis(50), &mut screen, &mut audio).unwrap();
+        assert_eq!(chip.cycles(), 30);
+        assert_eq!(chip.sound_timer(), 2);
+        assert_eq!(audio.transitions, [true]);
+        assert_eq!(screen.clears.len(), 1);
+
+        chip.service(Duration::from_millis(50), &mut screen, &mut audio).unwrap();
+        assert_eq!(chip.cycles(), 60);
+        assert_eq!(chip.sound_timer(), 0);
+        assert_eq!(audio.transitions, [true, false]);
+        assert_eq!(screen.clears.len(), 2);
+    }
+
+    #[test]
+    fn rom_title_reads_a_skipped_string() {
+        let mut rom = alloc::vec![0x12, 0x0C];
+        rom.extend_from_slice(b"PONG 1.0\0\0");
+        rom.extend_from_slice(&[0x00, 0xE0]);
+        assert_eq!(rom_title(&rom), Some("PONG 1.0".to_string()));
+
+        assert_eq!(rom_title(IBM_LOGO), None);
+        assert_eq!(rom_title(&[0x12, 0x06, 0xFF, 0xFE, 0x00, 0x00]), None);
+        let mut long = alloc::vec![0x13, 0x00];
+        long.extend(core::iter::repeat_n(b'A', 0xFE));
+        assert_eq!(rom_title(&long), None);
+        assert_eq!(rom_title(&[0x12]), None);
+    }
+
+    #[test]
+    fn every_opcode_decodes_or_errors_without_panicking() {
+        for opcode in 0..=u16::MAX {
+            match Instruction::try_from(opcode) {
+                Ok(instruction) => assert_eq!(u16::from(instruction), opcode),
+                Err(err) => assert_eq!(err, Chip8Error::UnknownInstruction(opcode)),
+            }
+            // executing may fail, e.g. on an empty stack or with I at the end of memory, but never panics
+            let mut chip = chip8(&[opcode]);
+            chip.set_index(0xFFF);
+            let _ = chip.step();
+        }
+    }
+
+    #[test]
+    fn hexdump_of_the_font() {
+        let chip = chip8(&[0x1200]);
+        let dump = chip.hexdump(FONT_BASE..FONT_BASE + FONT_LEN as u16).unwrap();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "0050  f0 90 90 90 f0 20 60 20  20 70 f0 10 f0 80 f0 f0  |..... `  p......|");
+        assert_eq!(chip.dump_memory(FONT_BASE..FONT_BASE + 5).unwrap(), [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert_eq!(chip.dump_memory(0xFFF..0x1001), Err(Chip8Error::OutOfBounds { addr: 0x1000, size: RAM_SIZE }));
+        assert_eq!(chip.hexdump(0x200..0x202).unwrap(), "0200  12 00                                             |..|\n");
+    }
+
+    #[test]
+    fn step_and_run_cycles_report_the_display_right_away() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let mut chip = chip8(&[0x6000, 0xF029, 0xD005, 0x7105, 0xD105, 0x120A]);
+        let recorded = Rc::clone(&frames);
+        chip.on_display_changed(Box::new(move |pixels| recorded.borrow_mut().push(pixels.iter().filter(|color| **color != 0).count())));
+        run(&mut chip, 2);
+        assert!(frames.borrow().is_empty());
+        run(&mut chip, 1);
+        assert_eq!(*frames.borrow(), [14]);
+
+        chip.run_cycles(3).unwrap();
+        assert_eq!(*frames.borrow(), [14, 28]);
+        chip.update().unwrap();
+        assert_eq!(*frames.borrow(), [14, 28]);
+    }
+
+    #[test]
+    fn key_tapped_within_a_frame_completes_wait_for_key() {
+        let mut chip = chip8(&[0xF30A, 0x1202]);
+        chip.set_ticks(10);
+        chip.queue_input(2, 0x7, true);
+        chip.queue_input(2, 0x7, false);
+        for _ in 0..2 {
+            chip.update().unwrap();
+            chip.tick_timers();
+        }
+        assert_eq!(chip.program_counter(), 0x200);
+        chip.update().unwrap();
+        assert_eq!((chip.program_counter(), chip.registers()[3]), (0x202, 0x7));
+
+        let mut chip = chip8(&[0xF30A, 0x1202]);
+        run(&mut chip, 1);
+        chip.on_input(0x7, true);
+        run(&mut chip, 1);
+        chip.on_input(0x7, false);
+        run(&mut chip, 1);
+        assert_eq!((chip.program_counter(), chip.registers()[3]), (0x202, 0x7));
+        assert_eq!(chip.step(), Ok(StepOutcome::Halted));
+    }
+
+    #[test]
+    fn rewind_keeps_host_settings_and_replays_queued_input() {
+        let mut chip = chip8(&[0xA050, 0xD015, 0xF30A, 0x7401, 0x1204]);
+        chip.set_ticks(10);
+        chip.set_rewind_capacity(4);
+        chip.queue_input(1, 0x5, true);
+        chip.queue_input(1, 0x5, false);
+        chip.update().unwrap();
+        chip.update().unwrap();
+        assert_eq!(chip.registers()[3], 0x5);
+        let screen = chip.screen().to_vec();
+
+        chip.set_fade(true);
+        chip.queue_input(5, 0x1, true);
+        assert!(chip.rewind());
+        assert_eq!((chip.frame_counter(), chip.registers()[3]), (1, 0));
+        assert_eq!(chip.screen(), screen);
+        assert_eq!(lit_pixels(&chip).len(), 14);
+        chip.execute_instruction(Instruction::ClearScreen).unwrap();
+        chip.tick_timers();
+        assert!(chip.screen_brightness().iter().any(|brightness| (1..u8::MAX).contains(brightness)));
+
+        chip.update().unwrap();
+        assert_eq!(chip.registers()[3], 0x5);
+        assert_eq!(chip.input_queue.keys().copied().collect::<Vec<_>>(), [5]);
+    }
+}
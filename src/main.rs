@@ -1,24 +1,147 @@
-use std::fs;
+use std::env;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Scancode;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
 
-use chip8_emulator::{Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_emulator::{Audio, Chip8, Palette, Rgb, Screen, LOWRES_HEIGHT, LOWRES_WIDTH};
 
 const SCALE: u32 = 10;
-const WIDTH: u32 = DISPLAY_WIDTH as u32 * SCALE;
-const HEIGHT: u32 = DISPLAY_HEIGHT as u32 * SCALE;
+/// High-res mode draws two CHIP-8 pixels per low-res pixel, so they need at least two window pixels.
+const MIN_SCALE: u32 = 2;
+const MAX_SCALE: u32 = 40;
 const TICKS: usize = 10;
+/// The timers and the screen run at 60Hz, `update` executes `ticks` instructions per frame.
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// After a stall, e.g. a dragged window, frames are dropped instead of caught up.
+const MAX_FRAME_LAG: u32 = 5;
+const DEFAULT_ROM: &str = "tests/roms/IBM Logo.ch8";
+const USAGE: &str = "usage: chip8-emulator [ROM] [--ticks N] [--scale N] [--debug]";
 
-const DEBUG: bool = false;
+struct CanvasScreen<'a> {
+    canvas: &'a mut Canvas<Window>,
+    pixel_size: u32,
+    debug: bool,
+    result: Result<(), String>,
+}
+
+impl Screen for CanvasScreen<'_> {
+    fn draw(&mut self, x: usize, y: usize, (r, g, b): Rgb) {
+        if self.debug {
+            println!("Box x:{x} y:{y}");
+        }
+        let size = self.pixel_size;
+        let rect = Rect::new(x as i32 * size as i32, y as i32 * size as i32, size, size);
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        if let Err(err) = self.canvas.fill_rect(rect) {
+            self.result = Err(err);
+        }
+    }
+
+    fn clear(&mut self, (r, g, b): Rgb) {
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+    }
+}
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = if self.phase <= 0.5 { self.volume } else { -self.volume };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+struct Beeper {
+    device: AudioDevice<SquareWave>,
+}
+
+impl Audio for Beeper {
+    fn set_playing(&mut self, playing: bool) {
+        if playing {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    rom: String,
+    ticks: usize,
+    /// Window pixels per low-res CHIP-8 pixel, clamped to 2-40.
+    scale: u32,
+    debug: bool,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut iter: I) -> Result<Args, String> {
+    let mut args = Args { rom: DEFAULT_ROM.to_string(), ticks: TICKS, scale: SCALE, debug: false };
+    let mut rom = None;
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--debug" => args.debug = true,
+            "--ticks" => {
+                let value = iter.next().ok_or(format!("--ticks needs a value\n{USAGE}"))?;
+                args.ticks = value.parse().map_err(|_| format!("invalid tick count {value}\n{USAGE}"))?;
+            }
+            "--scale" => {
+                let value = iter.next().ok_or(format!("--scale needs a value\n{USAGE}"))?;
+                args.scale = match value.parse::<u32>() {
+                    Ok(scale) => scale.clamp(MIN_SCALE, MAX_SCALE),
+                    Err(_) => {
+                        eprintln!("invalid scale {value}, using {SCALE}");
+                        SCALE
+                    }
+                };
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {arg}\n{USAGE}")),
+            _ if rom.is_none() => rom = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}\n{USAGE}")),
+        }
+    }
+
+    if let Some(rom) = rom {
+        args.rom = rom;
+    }
+    if !Path::new(&args.rom).is_file() {
+        return Err(format!("rom {} does not exist\n{USAGE}", args.rom));
+    }
+    Ok(args)
+}
+
+fn window_title(rom: &str) -> String {
+    match Path::new(rom).file_name() {
+        Some(name) => format!("chip8-emulator - {}", name.to_string_lossy()),
+        None => "chip8-emulator".to_string(),
+    }
+}
 
 fn main() -> Result<(), String> {
+    let args = parse_args(env::args().skip(1))?;
+    let (width, height) = (LOWRES_WIDTH as u32 * args.scale, LOWRES_HEIGHT as u32 * args.scale);
+    let title = window_title(&args.rom);
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
+    let audio_subsystem = sdl_context.audio()?;
     let window = video_subsystem
-        .window("chip8-emulator", WIDTH, HEIGHT)
+        .window(&title, width, height)
         .position_centered()
         .opengl()
         .build()
@@ -32,12 +155,24 @@ fn main() -> Result<(), String> {
     canvas.clear();
     canvas.present();
 
-    let mut emulator = Chip8::new(TICKS, DEBUG)?;
-    let rom = fs::read("roms/IBM Logo.ch8").map_err(|err| err.to_string())?;
+    let spec = AudioSpecDesired {
+        freq: Some(44_100),
+        channels: Some(1),
+        samples: None,
+    };
+    let device = audio_subsystem.open_playback(None, &spec, |spec| SquareWave {
+        phase_inc: 440.0 / spec.freq as f32,
+        phase: 0.0,
+        volume: 0.25,
+    })?;
+    let mut beeper = Beeper { device };
+    let palette = Palette::classic();
 
-    emulator.load_program(&rom)?;
+    let mut emulator = Chip8::new(args.ticks, args.debug).map_err(|err| err.to_string())?;
+    emulator.load_program_file(&args.rom).map_err(|err| err.to_string())?;
 
     let mut event_pump = sdl_context.event_pump()?;
+    let mut next_frame = Instant::now();
     'game: loop {
         for event in event_pump.poll_iter() {
             match event {
@@ -48,11 +183,16 @@ fn main() -> Result<(), String> {
                     scancode,
                     ..
                 } => {
-                    println!("Key down {:?}", event);
                     if let Scancode::Escape = scancode.unwrap() {
                         break 'game;
                     }
-                    if let Ok(key) = scancode_to_char(scancode.unwrap()) {
+                    if let Scancode::P = scancode.unwrap() {
+                        emulator.set_paused(!emulator.is_paused());
+                    }
+                    if let Ok(key) = scancode_to_key(scancode.unwrap()) {
+                        if args.debug {
+                            println!("Key down {:X} ({})", key, key_name(key));
+                        }
                         emulator.on_input(key, true);
                     }
                 }
@@ -60,62 +200,117 @@ fn main() -> Result<(), String> {
                     scancode,
                     ..
                 } => {
-                    println!("Key up {:?}", event);
                     if let Scancode::Escape = scancode.unwrap() {
                         break 'game;
                     }
-                    if let Ok(key) = scancode_to_char(scancode.unwrap()) {
+                    if let Ok(key) = scancode_to_key(scancode.unwrap()) {
+                        if args.debug {
+                            println!("Key up {:X} ({})", key, key_name(key));
+                        }
                         emulator.on_input(key, false);
                     }
                 }
                 _ => {}
             }
-            emulator.update()?;
-            let pixels = emulator.screen();
-            canvas.set_draw_color(Color::BLACK);
-            canvas.clear();
-
-            for i in 0..pixels.len() {
-                let pixel = pixels[i];
-                match pixel {
-                    true => canvas.set_draw_color(Color::WHITE),
-                    false => canvas.set_draw_color(Color::BLACK)
-                }
-
-                let y = (i / DISPLAY_WIDTH) as i32;
-                let x = (i % DISPLAY_WIDTH) as i32;
-                let rect = Rect::new(x * SCALE as i32, y * SCALE as i32, SCALE, SCALE);
-                if pixel && DEBUG{
-                    println!("Box x:{x} y:{y}");
-                }
-                canvas.fill_rect(rect)?;
-            }
+        }
 
-            canvas.present();
+        let now = Instant::now();
+        if now < next_frame {
+            thread::sleep(next_frame - now);
+            continue;
         }
+        next_frame = if now - next_frame > FRAME * MAX_FRAME_LAG { now + FRAME } else { next_frame + FRAME };
+
+        emulator.update().map_err(|err| err.to_string())?;
+        emulator.tick_timers();
+        emulator.render_audio(&mut beeper);
+
+        let (columns, _) = emulator.resolution();
+        let mut screen = CanvasScreen {
+            canvas: &mut canvas,
+            pixel_size: width / columns as u32,
+            debug: args.debug,
+            result: Ok(()),
+        };
+        emulator.render(&mut screen, &palette);
+        screen.result?;
+
+        canvas.present();
     }
     Ok(())
 }
 
-fn scancode_to_char(scancode: Scancode) -> Result<char, String> {
-    let key = match scancode {
-        Scancode::A => 'a',
-        Scancode::C => 'c',
-        Scancode::D => 'd',
-        Scancode::E => 'e',
-        Scancode::F => 'f',
-        Scancode::Q => 'q',
-        Scancode::R => 'r',
-        Scancode::S => 's',
-        Scancode::W => 'w',
-        Scancode::X => 'x',
-        Scancode::Y => 'y',
-        Scancode::Z => 'z',
-        Scancode::Num1 => '1',
-        Scancode::Num2 => '2',
-        Scancode::Num3 => '3',
-        Scancode::Num4 => '4',
-        _ => return Err(format!("invalid key input: {}", scancode.name()))
-    };
-    Ok(key)
-}
\ No newline at end of file
+/// Maps the left side of a QWERTY keyboard onto the CHIP-8 hex keypad:
+///
+/// ```text
+/// 1 2 3 4        1 2 3 C
+/// Q W E R   ->   4 5 6 D
+/// A S D F        7 8 9 E
+/// Z X C V        A 0 B F
+/// ```
+const KEYMAP: [(Scancode, u8); 16] = [
+    (Scancode::Num1, 0x1), (Scancode::Num2, 0x2), (Scancode::Num3, 0x3), (Scancode::Num4, 0xC),
+    (Scancode::Q, 0x4), (Scancode::W, 0x5), (Scancode::E, 0x6), (Scancode::R, 0xD),
+    (Scancode::A, 0x7), (Scancode::S, 0x8), (Scancode::D, 0x9), (Scancode::F, 0xE),
+    (Scancode::Z, 0xA), (Scancode::X, 0x0), (Scancode::C, 0xB), (Scancode::V, 0xF),
+];
+
+fn scancode_to_key(scancode: Scancode) -> Result<u8, String> {
+    KEYMAP.iter()
+        .find(|(mapped, _)| *mapped == scancode)
+        .map(|(_, key)| *key)
+        .ok_or_else(|| format!("invalid key input: {}", scancode.name()))
+}
+
+fn key_name(key: u8) -> &'static str {
+    KEYMAP.iter()
+        .find(|(_, mapped)| *mapped == key)
+        .map(|(scancode, _)| scancode.name())
+        .unwrap_or("?")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_rom_and_flags() {
+        let args = parse(&["--ticks", "20", "Cargo.toml", "--debug"]).unwrap();
+        assert_eq!((args.rom.as_str(), args.ticks, args.scale, args.debug), ("Cargo.toml", 20, SCALE, true));
+    }
+
+    #[test]
+    fn defaults_to_the_ibm_logo() {
+        let args = parse(&[]).unwrap();
+        assert_eq!((args.rom.as_str(), args.ticks, args.debug), (DEFAULT_ROM, TICKS, false));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["does/not/exist.ch8"]).unwrap_err().starts_with("rom does/not/exist.ch8 does not exist"));
+        assert!(parse(&["Cargo.toml", "--ticks"]).is_err());
+        assert!(parse(&["Cargo.toml", "--ticks", "fast"]).is_err());
+        assert!(parse(&["Cargo.toml", "--speed"]).is_err());
+        assert!(parse(&["Cargo.toml", "README.md"]).is_err());
+    }
+
+    #[test]
+    fn scale_is_clamped_or_falls_back() {
+        let scale = |value: &str| parse(&["Cargo.toml", "--scale", value]).unwrap().scale;
+        assert_eq!(scale("20"), 20);
+        assert_eq!(scale("100"), MAX_SCALE);
+        assert_eq!(scale("1"), MIN_SCALE);
+        assert_eq!(scale("big"), SCALE);
+        assert!(parse(&["Cargo.toml", "--scale"]).is_err());
+    }
+
+    #[test]
+    fn window_title_names_the_rom() {
+        assert_eq!(window_title("roms/IBM Logo.ch8"), "chip8-emulator - IBM Logo.ch8");
+        assert_eq!(window_title(".."), "chip8-emulator");
+    }
+}
@@ -0,0 +1,107 @@
+//! Runs the test ROMs in `tests/roms` for a fixed number of frames and compares the screen
+//! against a golden `display_hash`. Adding a ROM means dropping it into `tests/roms` and
+//! adding an entry to `ROMS`, a failing run prints the hash and the screen it ended with.
+//!
+//! Every ROM except the `extra` ones also needs a reference screenshot next to it, the ROM name
+//! with a `.txt` extension and one line per row with `#` for lit and `.` for dark pixels. The
+//! screen has to match it, so a golden hash can't be recorded from a wrong run.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8_emulator::{assemble, Chip8};
+
+const TICKS: usize = 10;
+const SEED: u64 = 1;
+/// The `display_hash` of the finished IBM logo.
+const IBM_LOGO_HASH: u64 = 0x1f1d_341c_ab07_e169;
+
+struct Rom {
+    /// File name in `tests/roms`.
+    name: &'static str,
+    /// Frames to run before comparing the screen.
+    frames: usize,
+    /// The expected `display_hash` afterwards.
+    hash: u64,
+    /// Keys tapped on the given frame, e.g. to pick an entry of a test suite menu.
+    keys: &'static [(usize, u8)],
+    /// Self-made regression ROMs, they have no reference screenshot and don't replace the
+    /// third-party test ROMs.
+    extra: bool,
+}
+
+const ROMS: &[Rom] = &[
+    Rom { name: "IBM Logo.ch8", frames: 20, hash: IBM_LOGO_HASH, keys: &[], extra: false },
+    Rom { name: "opcodes.ch8", frames: 60, hash: 0xc2b3_c2e1_3bee_9bb0, keys: &[], extra: true },
+];
+
+fn roms_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("roms")
+}
+
+fn run(rom: &Rom) -> Chip8 {
+    let name = rom.name;
+    let data = fs::read(roms_dir().join(name)).unwrap_or_else(|err| panic!("{name}: {err}"));
+    let mut chip = Chip8::builder().ticks(TICKS).seed(SEED).build().unwrap();
+    chip.load_program(&data).unwrap();
+    for (frame, key) in rom.keys {
+        chip.queue_input(*frame, *key, true);
+        chip.queue_input(*frame + 1, *key, false);
+    }
+    chip.run_frames(rom.frames).unwrap_or_else(|err| panic!("{name}: {err}"));
+    chip
+}
+
+/// Reads a reference screenshot into one lit flag per pixel.
+fn screenshot(path: &Path) -> Vec<bool> {
+    let text = fs::read_to_string(path).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+    text.lines().flat_map(|line| line.chars().map(|pixel| pixel == '#')).collect()
+}
+
+#[test]
+fn roms_match_their_golden_display_hash() {
+    let mut failures = Vec::new();
+    for rom in ROMS {
+        let chip = run(rom);
+        if chip.display_hash() != rom.hash {
+            failures.push(format!("{} ended with {:#018x} after {} frames:\n{}", rom.name, chip.display_hash(), rom.frames, chip.render_ascii()));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn roms_match_their_reference_screenshot() {
+    for rom in ROMS.iter().filter(|rom| !rom.extra) {
+        let chip = run(rom);
+        let lit: Vec<bool> = chip.screen().iter().map(|color| *color != 0).collect();
+        let expected = screenshot(&roms_dir().join(rom.name).with_extension("txt"));
+        assert!(lit == expected, "{} does not match its screenshot:\n{}", rom.name, chip.render_ascii());
+    }
+}
+
+#[test]
+fn ibm_logo_runs_until_it_halts() {
+    let rom = fs::read(roms_dir().join("IBM Logo.ch8")).unwrap();
+    let mut chip = Chip8::builder().ticks(TICKS).seed(SEED).build().unwrap();
+    chip.load_program(&rom).unwrap();
+    assert_eq!(chip.run_until(Chip8::is_halted, 100), Ok(true));
+    assert_eq!(chip.program_counter(), 0x228);
+    assert_eq!(chip.display_hash(), IBM_LOGO_HASH);
+}
+
+#[test]
+fn every_rom_has_a_golden_display_hash() {
+    for entry in fs::read_dir(roms_dir()).unwrap() {
+        let name = entry.unwrap().file_name().to_string_lossy().into_owned();
+        if name.ends_with(".ch8") {
+            assert!(ROMS.iter().any(|rom| rom.name == name), "{name} has no entry in ROMS");
+        }
+    }
+}
+
+#[test]
+fn opcodes_rom_is_assembled_from_its_source() {
+    let source = fs::read_to_string(roms_dir().join("opcodes.asm")).unwrap();
+    assert_eq!(assemble(&source).unwrap(), fs::read(roms_dir().join("opcodes.ch8")).unwrap());
+}
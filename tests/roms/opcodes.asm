@@ -0,0 +1,259 @@
+; Checks one opcode group per test and draws the low digit of the test number, or a block if it failed.
+; `assemble` has no labels, so they are comments and every address is absolute.
+; 7XNN
+SET V0 0x10     ; 0x200
+ADD V0 0x25     ; 0x202
+SET V3 0x01     ; 0x204 test 1
+FONT V3         ; 0x206
+SE V0 0x35      ; 0x208
+INDEX 0x3b8     ; 0x20a failed
+CALL 0x3a4      ; 0x20c
+; 8XY0
+SET V1 0x42     ; 0x20e
+SET V0 V1       ; 0x210
+SET V3 0x02     ; 0x212 test 2
+FONT V3         ; 0x214
+SE V0 0x42      ; 0x216
+INDEX 0x3b8     ; 0x218 failed
+CALL 0x3a4      ; 0x21a
+; 8XY1
+SET V0 0x0f     ; 0x21c
+SET V1 0xf0     ; 0x21e
+OR V0 V1        ; 0x220
+SET V3 0x03     ; 0x222 test 3
+FONT V3         ; 0x224
+SE V0 0xff      ; 0x226
+INDEX 0x3b8     ; 0x228 failed
+CALL 0x3a4      ; 0x22a
+; 8XY2
+SET V0 0x3c     ; 0x22c
+SET V1 0x0f     ; 0x22e
+AND V0 V1       ; 0x230
+SET V3 0x04     ; 0x232 test 4
+FONT V3         ; 0x234
+SE V0 0x0c      ; 0x236
+INDEX 0x3b8     ; 0x238 failed
+CALL 0x3a4      ; 0x23a
+; 8XY3
+SET V0 0x3c     ; 0x23c
+SET V1 0x0f     ; 0x23e
+XOR V0 V1       ; 0x240
+SET V3 0x05     ; 0x242 test 5
+FONT V3         ; 0x244
+SE V0 0x33      ; 0x246
+INDEX 0x3b8     ; 0x248 failed
+CALL 0x3a4      ; 0x24a
+; 8XY4 and its carry
+SET V0 0xff     ; 0x24c
+SET V1 0x02     ; 0x24e
+ADD V0 V1       ; 0x250
+SET V2 VF       ; 0x252
+SET V3 0x06     ; 0x254 test 6
+FONT V3         ; 0x256
+SE V0 0x01      ; 0x258
+INDEX 0x3b8     ; 0x25a failed
+CALL 0x3a4      ; 0x25c
+SET V3 0x07     ; 0x25e test 7
+FONT V3         ; 0x260
+SE V2 0x01      ; 0x262
+INDEX 0x3b8     ; 0x264 failed
+CALL 0x3a4      ; 0x266
+; 8XY5 and its borrow
+SET V0 0x05     ; 0x268
+SET V1 0x07     ; 0x26a
+SUB V0 V1       ; 0x26c
+SET V2 VF       ; 0x26e
+SET V3 0x08     ; 0x270 test 8
+FONT V3         ; 0x272
+SE V0 0xfe      ; 0x274
+INDEX 0x3b8     ; 0x276 failed
+CALL 0x3a4      ; 0x278
+SET V3 0x09     ; 0x27a test 9
+FONT V3         ; 0x27c
+SE V2 0x00      ; 0x27e
+INDEX 0x3b8     ; 0x280 failed
+CALL 0x3a4      ; 0x282
+; 8XY7 and its borrow
+SET V0 0x05     ; 0x284
+SET V1 0x07     ; 0x286
+SUBN V0 V1      ; 0x288
+SET V2 VF       ; 0x28a
+SET V3 0x0a     ; 0x28c test 10
+FONT V3         ; 0x28e
+SE V0 0x02      ; 0x290
+INDEX 0x3b8     ; 0x292 failed
+CALL 0x3a4      ; 0x294
+SET V3 0x0b     ; 0x296 test 11
+FONT V3         ; 0x298
+SE V2 0x01      ; 0x29a
+INDEX 0x3b8     ; 0x29c failed
+CALL 0x3a4      ; 0x29e
+; 8XY6 with VX = VY so the shift quirk does not matter
+SET V0 0x05     ; 0x2a0
+SHR V0 V0       ; 0x2a2
+SET V2 VF       ; 0x2a4
+SET V3 0x0c     ; 0x2a6 test 12
+FONT V3         ; 0x2a8
+SE V0 0x02      ; 0x2aa
+INDEX 0x3b8     ; 0x2ac failed
+CALL 0x3a4      ; 0x2ae
+SET V3 0x0d     ; 0x2b0 test 13
+FONT V3         ; 0x2b2
+SE V2 0x01      ; 0x2b4
+INDEX 0x3b8     ; 0x2b6 failed
+CALL 0x3a4      ; 0x2b8
+; 8XYE
+SET V0 0x81     ; 0x2ba
+SHL V0 V0       ; 0x2bc
+SET V2 VF       ; 0x2be
+SET V3 0x0e     ; 0x2c0 test 14
+FONT V3         ; 0x2c2
+SE V0 0x02      ; 0x2c4
+INDEX 0x3b8     ; 0x2c6 failed
+CALL 0x3a4      ; 0x2c8
+SET V3 0x0f     ; 0x2ca test 15
+FONT V3         ; 0x2cc
+SE V2 0x01      ; 0x2ce
+INDEX 0x3b8     ; 0x2d0 failed
+CALL 0x3a4      ; 0x2d2
+; 2NNN and 00EE
+SET V0 0x00     ; 0x2d4
+CALL 0x3b4      ; 0x2d6
+SET V3 0x00     ; 0x2d8 test 16
+FONT V3         ; 0x2da
+SE V0 0x07      ; 0x2dc
+INDEX 0x3b8     ; 0x2de failed
+CALL 0x3a4      ; 0x2e0
+; BNNN with X = 0 so the jump quirk does not matter
+SET V0 0x02     ; 0x2e2
+JUMP0 0x2e6     ; 0x2e4
+; skipped:
+SET V0 0xee     ; 0x2e6 jumped over
+SET V3 0x01     ; 0x2e8 test 17
+FONT V3         ; 0x2ea
+SE V0 0x02      ; 0x2ec
+INDEX 0x3b8     ; 0x2ee failed
+CALL 0x3a4      ; 0x2f0
+; 3XNN
+SET V0 0x01     ; 0x2f2
+SE V0 0x01      ; 0x2f4
+SET V0 0xee     ; 0x2f6
+SET V3 0x02     ; 0x2f8 test 18
+FONT V3         ; 0x2fa
+SE V0 0x01      ; 0x2fc
+INDEX 0x3b8     ; 0x2fe failed
+CALL 0x3a4      ; 0x300
+; 4XNN
+SET V0 0x01     ; 0x302
+SNE V0 0x02     ; 0x304
+SET V0 0xee     ; 0x306
+SET V3 0x03     ; 0x308 test 19
+FONT V3         ; 0x30a
+SE V0 0x01      ; 0x30c
+INDEX 0x3b8     ; 0x30e failed
+CALL 0x3a4      ; 0x310
+; 5XY0
+SET V0 0x03     ; 0x312
+SET V1 0x03     ; 0x314
+SE V0 V1        ; 0x316
+SET V0 0xee     ; 0x318
+SET V3 0x04     ; 0x31a test 20
+FONT V3         ; 0x31c
+SE V0 0x03      ; 0x31e
+INDEX 0x3b8     ; 0x320 failed
+CALL 0x3a4      ; 0x322
+; 9XY0
+SET V0 0x03     ; 0x324
+SET V1 0x04     ; 0x326
+SNE V0 V1       ; 0x328
+SET V0 0xee     ; 0x32a
+SET V3 0x05     ; 0x32c test 21
+FONT V3         ; 0x32e
+SE V0 0x03      ; 0x330
+INDEX 0x3b8     ; 0x332 failed
+CALL 0x3a4      ; 0x334
+; FX33 and FX65
+INDEX 0x3bd     ; 0x336
+SET V0 0xea     ; 0x338 234
+BCD V0          ; 0x33a
+LOAD V2         ; 0x33c
+SET V3 0x06     ; 0x33e test 22
+FONT V3         ; 0x340
+SE V0 0x02      ; 0x342
+INDEX 0x3b8     ; 0x344 failed
+CALL 0x3a4      ; 0x346
+SET V3 0x07     ; 0x348 test 23
+FONT V3         ; 0x34a
+SE V1 0x03      ; 0x34c
+INDEX 0x3b8     ; 0x34e failed
+CALL 0x3a4      ; 0x350
+SET V3 0x08     ; 0x352 test 24
+FONT V3         ; 0x354
+SE V2 0x04      ; 0x356
+INDEX 0x3b8     ; 0x358 failed
+CALL 0x3a4      ; 0x35a
+; FX55, the index is set again because of the load/store quirk
+SET V0 0x11     ; 0x35c
+SET V1 0x22     ; 0x35e
+INDEX 0x3bd     ; 0x360
+STORE V1        ; 0x362
+SET V1 0x00     ; 0x364
+INDEX 0x3bd     ; 0x366
+LOAD V1         ; 0x368
+SET V3 0x09     ; 0x36a test 25
+FONT V3         ; 0x36c
+SE V1 0x22      ; 0x36e
+INDEX 0x3b8     ; 0x370 failed
+CALL 0x3a4      ; 0x372
+; FX1E
+INDEX 0x3bd     ; 0x374
+SET V1 0x01     ; 0x376
+ADDI V1         ; 0x378
+LOAD V0         ; 0x37a
+SET V3 0x0a     ; 0x37c test 26
+FONT V3         ; 0x37e
+SE V0 0x22      ; 0x380
+INDEX 0x3b8     ; 0x382 failed
+CALL 0x3a4      ; 0x384
+; CXNN with an empty mask
+RAND V0 0x00    ; 0x386
+SET V3 0x0b     ; 0x388 test 27
+FONT V3         ; 0x38a
+SE V0 0x00      ; 0x38c
+INDEX 0x3b8     ; 0x38e failed
+CALL 0x3a4      ; 0x390
+; FX29
+SET V0 0x00     ; 0x392
+FONT V0         ; 0x394
+LOAD V0         ; 0x396
+SET V3 0x0c     ; 0x398 test 28
+FONT V3         ; 0x39a
+SE V0 0xf0      ; 0x39c
+INDEX 0x3b8     ; 0x39e failed
+CALL 0x3a4      ; 0x3a0
+; done:
+JUMP 0x3a2      ; 0x3a2 halt
+; draws the sprite at I to V5, V6 and moves to the next slot
+; show:
+DRAW V5 V6 5    ; 0x3a4
+ADD V5 0x05     ; 0x3a6
+SNE V5 0x3c     ; 0x3a8
+CALL 0x3ae      ; 0x3aa
+RET             ; 0x3ac
+; newline:
+SET V5 0x00     ; 0x3ae
+ADD V6 0x06     ; 0x3b0
+RET             ; 0x3b2
+; set7:
+SET V0 0x07     ; 0x3b4
+RET             ; 0x3b6
+; block:
+DB 0xf0         ; 0x3b8
+DB 0xf0         ; 0x3b9
+DB 0xf0         ; 0x3ba
+DB 0xf0         ; 0x3bb
+DB 0xf0         ; 0x3bc
+; scratch:
+DB 0x00         ; 0x3bd
+DB 0x00         ; 0x3be
+DB 0x00         ; 0x3bf